@@ -2,10 +2,20 @@
 
 mod code_generator;
 mod code_gen_structs;
+mod code_gen_instr;
+mod code_gen_printer;
 mod code_gen_expr;
 mod code_gen_stack;
-use crate::parsing::NodeRoot;
+mod code_gen_frame;
+mod code_gen_checks;
+mod code_gen_float;
+mod code_gen_array;
+mod code_gen_struct;
+mod code_gen_runtime;
+mod code_gen_regalloc;
+mod code_gen_peephole;
+use crate::{ir::IrModule, Options};
 
-pub fn generate(node_root: NodeRoot) {
-    code_generator::generate_code(node_root)
+pub fn generate(module: &IrModule, options: &Options) -> String {
+    code_generator::generate_code(module, options)
 }
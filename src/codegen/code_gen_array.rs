@@ -0,0 +1,70 @@
+use crate::ir::{LocalId, VReg};
+
+use super::{
+    code_gen_instr::{imm, label, reg, Operand},
+    code_gen_regalloc::{Location, Value},
+    code_gen_stack::{SCRATCH_LHS, SCRATCH_RHS, SCRATCH_TEMP},
+    code_gen_structs::Generator,
+};
+
+// Address of the first element
+const ARRAY_BASE: &str = SCRATCH_TEMP;
+
+impl Generator {
+    pub fn gen_load_index(&mut self, dst: VReg, local: LocalId, index: VReg) {
+        let index = self.use_reg(Value::VReg(index), SCRATCH_RHS);
+        self.check_bounds(index, self.array_len(local));
+        self.array_base(local);
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        self.emit("LDR", vec![reg(dst_reg), element(index)]);
+        self.finish_def(dst, dst_reg);
+    }
+
+    pub fn gen_store_index(&mut self, local: LocalId, index: VReg, src: VReg) {
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        let index = self.use_reg(Value::VReg(index), SCRATCH_RHS);
+        self.check_bounds(index, self.array_len(local));
+        self.array_base(local);
+        self.emit("STR", vec![reg(src), element(index)]);
+    }
+
+    // Counts down from the last element to the first
+    pub fn gen_fill(&mut self, local: LocalId, src: VReg) {
+        let len = self.array_len(local);
+        if len == 0 {
+            return;
+        }
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        self.array_base(local);
+        let counter = SCRATCH_RHS;
+        self.load_bits(counter, len as u64);
+        let top = self.local_label("fill");
+        self.label(&top);
+        self.emit("SUB", vec![reg(counter), reg(counter), imm(1)]);
+        self.emit("STR", vec![reg(src), element(counter)]);
+        self.emit("CBNZ", vec![reg(counter), label(&top)]);
+    }
+
+    fn array_len(&self, local: LocalId) -> usize {
+        self.m_locals[local.0].len.expect("Only arrays are indexed")
+    }
+
+    fn array_base(&mut self, local: LocalId) {
+        self.memory_address(ARRAY_BASE, local);
+    }
+
+    /// Loads into `dst` the address of an array or struct local.
+    pub fn memory_address(&mut self, dst: &str, local: LocalId) {
+        let Location::Slot(slot) = self.m_alloc.location(Value::Local(local)) else {
+            unreachable!("Arrays and structs always live on the stack")
+        };
+        let slots = self.m_locals[local.0].slots(&self.structs).expect("Only arrays and structs live in memory");
+        let offset = self.m_frame.memory_offset(slot, slots.max(1));
+        self.sub_immediate(dst, "X29", offset);
+    }
+}
+
+fn element(index: &str) -> Operand {
+    Operand::Indexed(ARRAY_BASE.to_owned(), index.to_owned())
+}
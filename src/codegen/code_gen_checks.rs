@@ -0,0 +1,165 @@
+use crate::ir::{BinaryOp, IrType};
+
+use super::{
+    code_gen_instr::{imm, label, reg, Cond, Instr, Operand},
+    code_gen_stack::SCRATCH_TEMP,
+    code_gen_structs::{w_reg, Generator},
+};
+
+/// Exit code used by every runtime trap, distinct from a clean `return`.
+pub const TRAP_EXIT_CODE: u8 = 101;
+
+const OVERFLOW_TRAP: &str = "_bk_trap_overflow";
+const DIVISION_BY_ZERO_TRAP: &str = "_bk_trap_div_zero";
+const BOUNDS_TRAP: &str = "_bk_trap_bounds";
+
+const OVERFLOW_MESSAGE: &str = "panic: attempt to compute arithmetic with overflow";
+const DIVISION_BY_ZERO_MESSAGE: &str = "panic: attempt to divide by zero";
+const BOUNDS_MESSAGE: &str = "panic: index out of bounds";
+
+// Upper half of a 64-bit product
+const PRODUCT_HIGH: &str = SCRATCH_TEMP;
+
+impl Generator {
+    /// `opcode`, or its flag setting form when the overflow check of `ty` reads the flags.
+    pub fn overflow_opcode(&self, opcode: &'static str, ty: IrType) -> &'static str {
+        match (opcode, self.runtime_checks && ty.bits() == 64) {
+            ("ADD", true) => "ADDS",
+            ("SUB", true) => "SUBS",
+            (opcode, _) => opcode,
+        }
+    }
+
+    // Narrow results are computed in 64 bits and only fit when extending their
+    // lower bits gives back the same value. 64-bit results are checked through
+    // the flags, or the upper half of the product for multiplications.
+    pub fn check_overflow(&mut self, register: &str, ty: IrType, op: &BinaryOp) {
+        if !self.runtime_checks {
+            return;
+        }
+        let extend = match ty {
+            IrType::I32 => "SXTW",
+            IrType::U32 => "UXTW",
+            IrType::U8 => "UXTB",
+            IrType::I64 | IrType::U64 => {
+                self.check_wide_overflow(register, ty, op);
+                return;
+            }
+            IrType::F64
+            | IrType::Bool
+            | IrType::Char
+            | IrType::Str
+            | IrType::Ptr(_)
+            | IrType::Struct(_)
+            | IrType::Unit => return,
+        };
+        // Unsigned quotients are never larger than the dividend
+        if *op == BinaryOp::Div && !ty.is_signed() {
+            return;
+        }
+        self.emit("CMP", vec![reg(register), reg(&w_reg(register)), Operand::Extend(extend)]);
+        self.buffer.push(Instr::BCond(Cond::Ne, OVERFLOW_TRAP.to_owned()));
+    }
+
+    fn check_wide_overflow(&mut self, register: &str, ty: IrType, op: &BinaryOp) {
+        let overflow = match (op, ty.is_signed()) {
+            (BinaryOp::Add | BinaryOp::Sub, true) => Cond::Vs,
+            // Carry out of an addition, borrow in a subtraction
+            (BinaryOp::Add, false) => Cond::Hs,
+            (BinaryOp::Sub, false) => Cond::Lo,
+            // The product fits when the upper half only repeats its sign bit
+            (BinaryOp::Mul, true) => {
+                self.emit("CMP", vec![reg(PRODUCT_HIGH), reg(register), Operand::Shift("ASR", 63)]);
+                Cond::Ne
+            }
+            (BinaryOp::Mul, false) => {
+                self.emit("CBNZ", vec![reg(PRODUCT_HIGH), label(OVERFLOW_TRAP)]);
+                return;
+            }
+            // Checked before the division
+            _ => return,
+        };
+        self.buffer.push(Instr::BCond(overflow, OVERFLOW_TRAP.to_owned()));
+    }
+
+    /// Computes the upper half of a 64-bit product for [`Generator::check_overflow`],
+    /// before the multiplication can overwrite an operand.
+    pub fn multiply_high(&mut self, ty: IrType, lhs: &str, rhs: &str) {
+        if !self.runtime_checks || ty.bits() != 64 {
+            return;
+        }
+        let opcode = if ty.is_signed() { "SMULH" } else { "UMULH" };
+        self.emit(opcode, vec![reg(PRODUCT_HIGH), reg(lhs), reg(rhs)]);
+    }
+
+    // i64::MIN / -1 does not fit, the hardware quietly returns i64::MIN.
+    // lhs - 1 only overflows for i64::MIN, it is compared when rhs is -1.
+    pub fn check_division_overflow(&mut self, ty: IrType, lhs: &str, rhs: &str) {
+        if !self.runtime_checks || ty != IrType::I64 {
+            return;
+        }
+        self.emit("CMN", vec![reg(rhs), imm(1)]);
+        self.emit("CCMP", vec![reg(lhs), imm(1), imm(0), Operand::Cond(Cond::Eq)]);
+        self.buffer.push(Instr::BCond(Cond::Vs, OVERFLOW_TRAP.to_owned()));
+    }
+
+    pub fn check_division_by_zero(&mut self, divisor: &str) {
+        if !self.runtime_checks {
+            return;
+        }
+        self.emit("CBZ", vec![reg(divisor), label(DIVISION_BY_ZERO_TRAP)]);
+    }
+
+    // Negative indices are sign extended, compared unsigned they are past any length
+    pub fn check_bounds(&mut self, index: &str, len: usize) {
+        if !self.runtime_checks {
+            return;
+        }
+        // CMP takes 12-bit immediates
+        if len < 1 << 12 {
+            self.emit("CMP", vec![reg(index), imm(len as i64)]);
+        } else {
+            self.load_bits(PRODUCT_HIGH, len as u64);
+            self.emit("CMP", vec![reg(index), reg(PRODUCT_HIGH)]);
+        }
+        self.buffer.push(Instr::BCond(Cond::Hs, BOUNDS_TRAP.to_owned()));
+    }
+
+    /// Emits the trap routines and their messages. Each trap writes its
+    /// message to stderr and exits with [`TRAP_EXIT_CODE`].
+    pub fn gen_traps(&mut self) {
+        if !self.runtime_checks {
+            return;
+        }
+        let traps = [
+            (OVERFLOW_TRAP, OVERFLOW_MESSAGE),
+            (DIVISION_BY_ZERO_TRAP, DIVISION_BY_ZERO_MESSAGE),
+            (BOUNDS_TRAP, BOUNDS_MESSAGE),
+        ];
+
+        for (trap, message) in traps.iter() {
+            let message_label = format!("{}_msg", trap);
+            self.label(trap);
+            self.emit("ADRP", vec![reg("X1"), Operand::Page(message_label.clone())]);
+            self.emit("ADD", vec![reg("X1"), reg("X1"), Operand::PageOff(message_label)]);
+            // +1 for the trailing new line
+            self.emit("MOV", vec![reg("X2"), imm(message.len() as i64 + 1)]);
+            self.emit("B", vec![label("_bk_trap")]);
+        }
+
+        self.comment("write(stderr, X1, X2) then exit");
+        self.label("_bk_trap");
+        self.emit("MOV", vec![reg("X0"), imm(2)]);
+        self.emit("MOV", vec![reg("X16"), imm(4)]);
+        self.emit("SVC", vec![imm(0x80)]);
+        self.emit("MOV", vec![reg("X0"), imm(TRAP_EXIT_CODE as i64)]);
+        self.emit("MOV", vec![reg("X16"), imm(1)]);
+        self.emit("SVC", vec![imm(0x80)]);
+
+        self.directive(".data");
+        for (trap, message) in traps.iter() {
+            self.label(&format!("{}_msg", trap));
+            self.directive(&format!(".ascii \"{}\\n\"", message));
+        }
+    }
+}
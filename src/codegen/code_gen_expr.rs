@@ -1,164 +1,253 @@
-use tracing::debug;
+use crate::ir::{BinaryOp, IrInst, IrType, VReg};
 
-use crate::
-    parsing::{
-        NodeBiExpr, NodeExpr, NodeTermExpr,
-    }
-;
+use super::{
+    code_gen_instr::{imm, label, reg, Cond, Operand},
+    code_gen_regalloc::Value,
+    code_gen_stack::{SCRATCH_LHS, SCRATCH_RHS},
+    code_gen_structs::{w_reg, Generator},
+};
 
-use super::code_gen_structs::Generator;
+pub fn string_label(index: usize) -> String {
+    format!("_bk_str{}", index)
+}
 
+pub fn global_label(index: usize) -> String {
+    format!("_bk_global{}", index)
+}
 
 impl Generator {
-    pub fn parse_expr(&mut self, expr: &NodeExpr) {
-        match expr {
-            NodeExpr::BinaryExpr(binary_expr) => {
-                self.parse_binary_expr(&binary_expr);
+    pub fn gen_inst(&mut self, inst: &IrInst) {
+        match inst {
+            IrInst::Const { dst, value } => {
+                let ty = self.m_vreg_types[dst.0];
+                let dst = Value::VReg(*dst);
+                let dst_reg = self.def_reg(dst);
+                // MOV takes 16 bits, shifted or inverted
+                match ty {
+                    IrType::F64 => self.load_bits(dst_reg, *value as u64),
+                    _ if (-0x10000..0x10000).contains(value) => self.emit("MOV", vec![reg(dst_reg), imm(*value)]),
+                    _ => self.load_bits(dst_reg, *value as u64),
+                }
+                self.finish_def(dst, dst_reg);
+            }
+            IrInst::Binary { dst, op, lhs, rhs } => {
+                self.gen_binary(*dst, op, *lhs, *rhs);
+            }
+            IrInst::Load { dst, local } => {
+                let reg = self.use_reg(Value::Local(*local), SCRATCH_LHS);
+                self.move_into(Value::VReg(*dst), reg);
+            }
+            IrInst::Store { local, src } => {
+                let reg = self.use_reg(Value::VReg(*src), SCRATCH_LHS);
+                self.move_into(Value::Local(*local), reg);
+            }
+            IrInst::LoadGlobal { dst, global } => {
+                self.gen_load_global(*dst, *global);
+            }
+            IrInst::StoreGlobal { global, src } => {
+                self.gen_store_global(*global, *src);
+            }
+            IrInst::LoadIndex { dst, local, index } => {
+                self.gen_load_index(*dst, *local, *index);
+            }
+            IrInst::StoreIndex { local, index, src } => {
+                self.gen_store_index(*local, *index, *src);
+            }
+            IrInst::Fill { local, src } => {
+                self.gen_fill(*local, *src);
+            }
+            IrInst::Addr { dst, local } => {
+                self.gen_addr(*dst, *local);
+            }
+            IrInst::FieldAddr { dst, base, offset } => {
+                self.gen_field_addr(*dst, *base, *offset);
             }
-            NodeExpr::Term(rhs_term) => {
-                self.parse_term(&rhs_term);
+            IrInst::LoadField { dst, base, offset } => {
+                self.gen_load_field(*dst, *base, *offset);
             }
-            NodeExpr::Call(f_name) => {
-                self.buffer.push(format!("MOV X29, X30\n"));
-                self.buffer.push(format!("BL _{}\n", f_name));
-                self.buffer.push(format!("MOV X30, X29\n"));
-                self.push("X0");
+            IrInst::StoreField { base, offset, src } => {
+                self.gen_store_field(*base, *offset, *src);
+            }
+            IrInst::Copy { dst, src, size } => {
+                self.gen_copy(*dst, *src, *size);
+            }
+            IrInst::Call { dst, func, args } => {
+                self.gen_args(args);
+                self.emit("BL", vec![label(&format!("_{}", func))]);
+                if self.m_vreg_types[dst.0] != IrType::Unit {
+                    self.move_into(Value::VReg(*dst), "X0");
+                }
+            }
+            IrInst::Cast { dst, src } => {
+                self.gen_cast(*dst, *src);
+            }
+            IrInst::Print { src, newline } => {
+                self.gen_print(*src, *newline);
+            }
+            IrInst::Read { dst } => {
+                self.gen_read(*dst);
+            }
+            IrInst::Str { dst, index } => {
+                let dst = Value::VReg(*dst);
+                let dst_reg = self.def_reg(dst);
+                let name = string_label(*index);
+                self.emit("ADRP", vec![reg(dst_reg), Operand::Page(name.clone())]);
+                self.emit("ADD", vec![reg(dst_reg), reg(dst_reg), Operand::PageOff(name)]);
+                self.finish_def(dst, dst_reg);
             }
         }
     }
 
-    fn parse_binary_expr(&mut self, binary_expr: &NodeBiExpr) {
-        let lhs = &binary_expr.lhs;
-        let rhs = &binary_expr.rhs;
-        self.parse_expr(&lhs);
-        let lhs_pos = self.current_stack_pointer();
-        self.parse_expr(&rhs);
-        self.pop("X2");
-        self.pop("X1");
-
-        //let lhs_pos = self.current_stack_pointer() - lhs_pos;
-
-        //self.pop_with_offset("X1", lhs_pos);
-
-        match binary_expr.op {
-            crate::parsing::NodeBiOp::Add => {
-                self.buffer.push(format!("ADD X1, X1, X2\n"));
-                self.push("X1");
-            }
-            crate::parsing::NodeBiOp::Multiply => {
-                self.buffer.push(format!("MUL X1, X1, X2\n"));
-                self.push("X1");
-            }
-            crate::parsing::NodeBiOp::Subtract => {
-                self.buffer.push(format!("SUBS X1, X1, X2\n"));
-                self.push("X1");
-            }
-            crate::parsing::NodeBiOp::Division => {
-                self.buffer.push(format!("SDIV X1, X1, X2\n"));
-                self.push("X1");
-            }
-            crate::parsing::NodeBiOp::Equality => {
-                // X1 has LHS value
-                // X2 has RHS values
-                // X1 will have result
-                // CSET will set X1 to 1 if previous operation has set ZERO flag
-                // eq will return 1 if previous operation result is 0.
-                self.buffer_push(&format!("SUBS X1, X1, X2"));
-                self.buffer_push(&format!("CSET X1, eq"));
-                self.push("X1");
-                self.comment("Equality finsihed");
-            }
-            crate::parsing::NodeBiOp::OR => {
-                // X1 (LHS) has 1 or 0
-                // X2 (RHS) has 1 or 0
-                //
-                // MOV X0, #0  ; W0 = 10 (true)
-                self.buffer_push(&format!("MOV X0, #0"));
-                self.buffer_push(&format!("CMN X1, X2"));
-                self.buffer_push(&format!("CSET X0, NE"));
-                self.buffer_push(&format!("ORR X0, X0, X2"));
+    // Arguments are passed in X0..X7, which are never allocated
+    pub fn gen_args(&mut self, args: &[VReg]) {
+        for (index, arg) in args.iter().enumerate() {
+            let src = self.use_reg(Value::VReg(*arg), SCRATCH_LHS);
+            self.emit("MOV", vec![reg(&format!("X{}", index)), reg(src)]);
+        }
+    }
 
-                self.push("X0");
-                self.comment("OR finsihed");
+    /// MOVZ starts from zeros and MOVN from ones, whichever leaves fewer 16-bit
+    /// pieces for MOVK to fill in.
+    pub fn load_bits(&mut self, register: &str, bits: u64) {
+        let chunks: Vec<(u32, u64)> = (0..64).step_by(16).map(|shift| (shift, (bits >> shift) & 0xffff)).collect();
+        let ones = chunks.iter().filter(|(_, chunk)| *chunk == 0xffff).count();
+        let zeros = chunks.iter().filter(|(_, chunk)| *chunk == 0).count();
+        let (fill, first_opcode) = if ones > zeros { (0xffff, "MOVN") } else { (0, "MOVZ") };
+
+        let mut pieces = chunks.into_iter().filter(|(_, chunk)| *chunk != fill).peekable();
+        if pieces.peek().is_none() {
+            self.emit("MOV", vec![reg(register), imm(if fill == 0 { 0 } else { -1 })]);
+        }
+        let mut opcode = first_opcode;
+        for (shift, chunk) in pieces {
+            let chunk = if opcode == "MOVN" { !chunk & 0xffff } else { chunk };
+            let mut operands = vec![reg(register), imm(chunk as i64)];
+            if shift != 0 {
+                operands.push(Operand::Shift("LSL", shift));
             }
-            crate::parsing::NodeBiOp::AND => {
-
-                self.buffer_push(&format!("MOV X3, #0"));
-                self.buffer_push(&format!("MOV X4, #0"));
-
-                self.buffer_push(&format!("CMN X1, X2"));
-                self.buffer_push(&format!("CSET X3, NE"));
-                self.buffer_push(&format!("CMN X1, X1"));
-                self.buffer_push(&format!("CSET X4, NE"));
-                self.buffer_push(&format!("AND X3, X3, X4"));
-
-                self.push("X3");
-                self.comment("AND finsihed");
-            }
-            crate::parsing::NodeBiOp::GreaterThan => {
-                self.buffer_push(&format!("CMP X1, X2"));
-                self.buffer_push(&format!("CSET X1, gt"));
-                self.push("X1");
-                self.comment("Greater than finsihed");
-            },
-            crate::parsing::NodeBiOp::LessThan => {
-                self.buffer_push(&format!("CMP X1, X2"));
-                self.buffer_push(&format!("CSET X1, lt"));
-                self.push("X1");
-                self.comment("Less than finsihed");
-            },
-            crate::parsing::NodeBiOp::LessThanEqual => {
-                self.buffer_push(&format!("CMP X1, X2"));
-                self.buffer_push(&format!("CSET X1, le"));
-                self.push("X1");
-                self.comment("Less than Equal finsihed");
-            },
-            crate::parsing::NodeBiOp::GreaterThanEqual => {
-                self.buffer_push(&format!("CMP X1, X2"));
-                self.buffer_push(&format!("CSET X1, ge"));
-                self.push("X1");
-                self.comment("Greater than Equal finsihed");
-            },
+            self.emit(opcode, operands);
+            opcode = "MOVK";
         }
     }
 
-    fn parse_term(&mut self, term: &NodeTermExpr) {
-        match term {
-            crate::parsing::NodeTermExpr::IntLiteral(value) => {
-                self.buffer.push(format!("MOV X1, #{}\n", value));
-                self.push("X1");
-            }
-            crate::parsing::NodeTermExpr::Identifier(value) => {
-                let map_value = self
-                    .get_variable(&value)
-                    .expect(&format!("Undefined variable {}", value));
-
-                let variable_location =  map_value.stack_location; 
-
+    fn gen_binary(&mut self, dst: VReg, op: &BinaryOp, lhs: VReg, rhs: VReg) {
+        // Both operands have the type of an arithmetic result
+        let ty = self.m_vreg_types[lhs.0];
+        let lhs = self.use_reg(Value::VReg(lhs), SCRATCH_LHS);
+        let rhs = self.use_reg(Value::VReg(rhs), SCRATCH_RHS);
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        if ty == IrType::F64 {
+            self.gen_float_binary(dst_reg, op, lhs, rhs);
+            self.finish_def(dst, dst_reg);
+            return;
+        }
+        let operands = vec![reg(dst_reg), reg(lhs), reg(rhs)];
 
-                let m_stack = self.current_stack_pointer();
-                let offset = m_stack - variable_location;
-                let offset = offset * 16;
+        match op {
+            BinaryOp::Add => {
+                self.emit(self.overflow_opcode("ADD", ty), operands);
+                self.check_overflow(dst_reg, ty, op);
+                self.truncate(dst_reg, ty);
+            }
+            BinaryOp::Mul => {
+                self.multiply_high(ty, lhs, rhs);
+                self.emit("MUL", operands);
+                self.check_overflow(dst_reg, ty, op);
+                self.truncate(dst_reg, ty);
+            }
+            BinaryOp::Sub => {
+                self.emit(self.overflow_opcode("SUB", ty), operands);
+                self.check_overflow(dst_reg, ty, op);
+                self.truncate(dst_reg, ty);
+            }
+            BinaryOp::Div => {
+                self.check_division_by_zero(rhs);
+                if ty.is_signed() {
+                    self.check_division_overflow(ty, lhs, rhs);
+                    self.emit("SDIV", operands);
+                } else {
+                    self.emit("UDIV", operands);
+                }
+                // i32::MIN / -1 is the only quotient that leaves the i32 range
+                self.check_overflow(dst_reg, ty, op);
+                self.truncate(dst_reg, ty);
+            }
+            BinaryOp::Eq => self.gen_compare(dst_reg, lhs, rhs, Cond::Eq, ty),
+            BinaryOp::Gt => self.gen_compare(dst_reg, lhs, rhs, Cond::Gt, ty),
+            BinaryOp::Lt => self.gen_compare(dst_reg, lhs, rhs, Cond::Lt, ty),
+            BinaryOp::Le => self.gen_compare(dst_reg, lhs, rhs, Cond::Le, ty),
+            BinaryOp::Ge => self.gen_compare(dst_reg, lhs, rhs, Cond::Ge, ty),
+            BinaryOp::Or => {
+                // Any non zero operand is true
+                self.emit("ORR", operands);
+                self.emit("CMP", vec![reg(dst_reg), imm(0)]);
+                self.emit("CSET", vec![reg(dst_reg), Operand::Cond(Cond::Ne)]);
+            }
+            BinaryOp::And => {
+                // Compare rhs only when lhs is non zero, otherwise force the Z flag
+                self.emit("CMP", vec![reg(lhs), imm(0)]);
+                self.emit("CCMP", vec![reg(rhs), imm(0), imm(4), Operand::Cond(Cond::Ne)]);
+                self.emit("CSET", vec![reg(dst_reg), Operand::Cond(Cond::Ne)]);
+            }
+        }
 
-                self.buffer.push(format!("LDP X1, X2, [SP, #{}]\n", offset));
-                self.push("X1");
+        self.finish_def(dst, dst_reg);
+    }
 
-                self.comment(&format!("Identifier {}, parse m_stack:{}, variable_location:{}, offset:{}, variable:{}", self.current_stack_pointer(), m_stack, variable_location, offset, value));
+    // Wraps a result back into its width, unless a check already trapped on it.
+    // Values are kept sign extended for signed types and zero extended otherwise.
+    fn truncate(&mut self, register: &str, ty: IrType) {
+        if !self.runtime_checks {
+            self.narrow(register, register, ty);
+        }
+    }
 
-            }
-            NodeTermExpr::Expression(expr) => {
-                self.parse_expr(&expr);
-            }
+    // The 64-bit registers hold every value already extended, so a 64-bit
+    // target or a source that fits the target only needs a move
+    fn gen_cast(&mut self, dst: VReg, src: VReg) {
+        let (from, to) = (self.m_vreg_types[src.0], self.m_vreg_types[dst.0]);
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        let dst = Value::VReg(dst);
+        if from == IrType::F64 || to == IrType::F64 {
+            let dst_reg = self.def_reg(dst);
+            self.gen_float_cast(dst_reg, src, from, to);
+            self.finish_def(dst, dst_reg);
+            return;
+        }
+        let ((from_min, from_max), (to_min, to_max)) = (from.range(), to.range());
+        if to.bits() == 64 || (to_min <= from_min && from_max <= to_max) {
+            self.move_into(dst, src);
+            return;
+        }
+        let dst_reg = self.def_reg(dst);
+        self.narrow(dst_reg, src, to);
+        self.finish_def(dst, dst_reg);
+    }
 
-            NodeTermExpr::BooleanLiteral(value) => {
-                let value: i8 = if *value {
-                    1
-                } else {
-                    0
-                };
-                self.buffer_push(&format!("MOV X1, {}", value));
-                self.push("X1");
+    // Extends the low bits of `src` that make up a `ty` into `dst`
+    fn narrow(&mut self, dst: &str, src: &str, ty: IrType) {
+        match ty {
+            IrType::I32 => self.emit("SXTW", vec![reg(dst), reg(&w_reg(src))]),
+            IrType::U8 | IrType::U32 | IrType::Char => {
+                let mask = (1i64 << ty.bits()) - 1;
+                self.emit("AND", vec![reg(dst), reg(src), imm(mask)]);
             }
+            IrType::I64
+            | IrType::U64
+            | IrType::F64
+            | IrType::Bool
+            | IrType::Str
+            | IrType::Ptr(_)
+            | IrType::Struct(_)
+            | IrType::Unit => {}
         }
     }
+
+    fn gen_compare(&mut self, dst: &str, lhs: &str, rhs: &str, cond: Cond, ty: IrType) {
+        let cond = if ty.is_signed() { cond } else { cond.unsigned() };
+        // CSET sets the register to 1 when the condition holds
+        self.emit("CMP", vec![reg(lhs), reg(rhs)]);
+        self.emit("CSET", vec![reg(dst), Operand::Cond(cond)]);
+    }
 }
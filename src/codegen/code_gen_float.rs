@@ -0,0 +1,73 @@
+use crate::ir::{BinaryOp, IrType};
+
+use super::{
+    code_gen_instr::{imm, reg, Cond, Operand},
+    code_gen_stack::SCRATCH_LHS,
+    code_gen_structs::{w_reg, Generator},
+};
+
+// f64 values are kept as bits in the general registers, like every other
+// value, so the allocator and the stack slots handle them unchanged. Only the
+// arithmetic moves them through these.
+const FLOAT_LHS: &str = "D16";
+const FLOAT_RHS: &str = "D17";
+
+impl Generator {
+    pub fn gen_float_binary(&mut self, dst: &str, op: &BinaryOp, lhs: &str, rhs: &str) {
+        self.emit("FMOV", vec![reg(FLOAT_LHS), reg(lhs)]);
+        self.emit("FMOV", vec![reg(FLOAT_RHS), reg(rhs)]);
+        let cond = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                let opcode = match op {
+                    BinaryOp::Add => "FADD",
+                    BinaryOp::Sub => "FSUB",
+                    BinaryOp::Mul => "FMUL",
+                    _ => "FDIV",
+                };
+                self.emit(opcode, vec![reg(FLOAT_LHS), reg(FLOAT_LHS), reg(FLOAT_RHS)]);
+                self.emit("FMOV", vec![reg(dst), reg(FLOAT_LHS)]);
+                return;
+            }
+            BinaryOp::Eq => Cond::Eq,
+            BinaryOp::Lt => Cond::Lt,
+            BinaryOp::Le => Cond::Le,
+            BinaryOp::Gt => Cond::Gt,
+            BinaryOp::Ge => Cond::Ge,
+            BinaryOp::Or | BinaryOp::And => unreachable!("Logical operators are not defined for f64"),
+        };
+        self.emit("FCMP", vec![reg(FLOAT_LHS), reg(FLOAT_RHS)]);
+        self.emit("CSET", vec![reg(dst), Operand::Cond(cond.ordered())]);
+    }
+
+    /// Casts between f64 and the integer types. Floats saturate at the bounds
+    /// of the integer type and NaN becomes 0.
+    pub fn gen_float_cast(&mut self, dst: &str, src: &str, from: IrType, to: IrType) {
+        if to == IrType::F64 {
+            let opcode = if from.is_signed() { "SCVTF" } else { "UCVTF" };
+            self.emit(opcode, vec![reg(FLOAT_LHS), reg(src)]);
+            self.emit("FMOV", vec![reg(dst), reg(FLOAT_LHS)]);
+            return;
+        }
+
+        self.emit("FMOV", vec![reg(FLOAT_LHS), reg(src)]);
+        // The 32-bit forms saturate at the 32-bit bounds and clear the upper half
+        match to {
+            IrType::I64 => self.emit("FCVTZS", vec![reg(dst), reg(FLOAT_LHS)]),
+            IrType::U64 => self.emit("FCVTZU", vec![reg(dst), reg(FLOAT_LHS)]),
+            IrType::U32 => self.emit("FCVTZU", vec![reg(&w_reg(dst)), reg(FLOAT_LHS)]),
+            IrType::I32 => {
+                self.emit("FCVTZS", vec![reg(&w_reg(dst)), reg(FLOAT_LHS)]);
+                self.emit("SXTW", vec![reg(dst), reg(&w_reg(dst))]);
+            }
+            IrType::U8 => {
+                self.emit("FCVTZU", vec![reg(&w_reg(dst)), reg(FLOAT_LHS)]);
+                self.emit("MOV", vec![reg(SCRATCH_LHS), imm(u8::MAX as i64)]);
+                self.emit("CMP", vec![reg(dst), reg(SCRATCH_LHS)]);
+                self.emit("CSEL", vec![reg(dst), reg(dst), reg(SCRATCH_LHS), Operand::Cond(Cond::Ls)]);
+            }
+            IrType::F64 | IrType::Bool | IrType::Char | IrType::Str | IrType::Ptr(_) | IrType::Struct(_) | IrType::Unit => {
+                unreachable!("{} is not cast from f64", to)
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::{
+    code_gen_instr::{imm, mem, reg, AddressMode, Operand},
+    code_gen_regalloc::Allocation,
+    code_gen_stack::SCRATCH_TEMP,
+    code_gen_structs::Generator,
+};
+
+/// Fixed frame of a function, laid out before any of its code is emitted.
+///
+/// ```text
+///   caller frame
+///   X29, X30            <- X29 (FP) after the prologue
+///   callee saved regs   8 bytes each
+///   slots               8 bytes each, an array takes one per element
+///   padding             total below FP is a multiple of 16
+///                       <- SP, untouched until the epilogue
+/// ```
+#[derive(Debug, Default)]
+pub struct FrameLayout {
+    pub callee_saved: Vec<&'static str>,
+    /// Bytes reserved below FP
+    pub size: usize,
+}
+
+impl FrameLayout {
+    pub fn new(alloc: &Allocation) -> FrameLayout {
+        let callee_saved = alloc.callee_saved.clone();
+        let used = (callee_saved.len() + alloc.slot_count) * 8;
+        let size = used.div_ceil(16) * 16;
+        FrameLayout { callee_saved, size }
+    }
+
+    // Distance below FP of the n-th callee saved register
+    fn callee_saved_offset(&self, index: usize) -> usize {
+        (index + 1) * 8
+    }
+
+    /// Distance below FP of a slot.
+    pub fn slot_offset(&self, slot: usize) -> usize {
+        (self.callee_saved.len() + slot + 1) * 8
+    }
+
+    /// Distance below FP of the first byte of an array or struct taking `len`
+    /// slots from `slot`. Slots grow downwards, so the start is in the last
+    /// slot and the rest follows it upwards.
+    pub fn memory_offset(&self, slot: usize, len: usize) -> usize {
+        self.slot_offset(slot + len - 1)
+    }
+}
+
+impl Generator {
+    /// Memory operand for the slot `offset` bytes below FP. Far slots get
+    /// their address computed into [`SCRATCH_TEMP`] first.
+    pub fn fp_address(&mut self, offset: usize) -> Operand {
+        // LDUR/STUR reach 256 bytes below the base register
+        if offset <= 256 {
+            return mem("X29", -(offset as i64), AddressMode::Offset);
+        }
+        self.sub_immediate(SCRATCH_TEMP, "X29", offset);
+        mem(SCRATCH_TEMP, 0, AddressMode::Offset)
+    }
+
+    /// SUB only takes 12-bit immediates, optionally shifted by 12.
+    pub fn sub_immediate(&mut self, dst: &str, src: &str, value: usize) {
+        assert!(value < 1 << 24, "Stack frame of {} bytes is too large", value);
+        let (high, low) = (value >> 12, value & 0xfff);
+        let mut src = src;
+        if high > 0 {
+            self.emit("SUB", vec![reg(dst), reg(src), imm(high as i64), Operand::Shift("LSL", 12)]);
+            src = dst;
+        }
+        if low > 0 || src != dst {
+            self.emit("SUB", vec![reg(dst), reg(src), imm(low as i64)]);
+        }
+    }
+
+    pub fn gen_prologue(&mut self) {
+        self.emit("STP", vec![reg("X29"), reg("X30"), mem("SP", -16, AddressMode::PreIndex)]);
+        self.emit("MOV", vec![reg("X29"), reg("SP")]);
+        if self.m_frame.size > 0 {
+            self.sub_immediate("SP", "SP", self.m_frame.size);
+        }
+        self.save_callee_saved("STP", "STR");
+    }
+
+    pub fn gen_epilogue(&mut self) {
+        self.save_callee_saved("LDP", "LDR");
+        self.emit("MOV", vec![reg("SP"), reg("X29")]);
+        self.emit("LDP", vec![reg("X29"), reg("X30"), mem("SP", 16, AddressMode::PostIndex)]);
+    }
+
+    // Pairs go through STP/LDP, a trailing odd register through STR/LDR
+    fn save_callee_saved(&mut self, pair_op: &'static str, single_op: &'static str) {
+        let registers = self.m_frame.callee_saved.clone();
+        for (index, pair) in registers.chunks(2).enumerate() {
+            let first = index * 2;
+            match pair {
+                [low, high] => {
+                    // STP puts its first register at the lower address
+                    let offset = self.m_frame.callee_saved_offset(first + 1);
+                    let address = mem("X29", -(offset as i64), AddressMode::Offset);
+                    self.emit(pair_op, vec![reg(high), reg(low), address]);
+                }
+                [single] => {
+                    let offset = self.m_frame.callee_saved_offset(first);
+                    let address = mem("X29", -(offset as i64), AddressMode::Offset);
+                    self.emit(single_op, vec![reg(single), address]);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
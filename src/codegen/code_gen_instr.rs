@@ -0,0 +1,134 @@
+/// One line of the generated assembly, rendered by the printer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Op(&'static str, Vec<Operand>),
+    /// `B.<cond> label`
+    BCond(Cond, String),
+    Label(String),
+    Comment(String),
+    Directive(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// `X12`, `W12`, `SP`, `XZR`
+    Reg(String),
+    /// `#5`
+    Imm(i64),
+    Label(String),
+    /// `label@PAGE`
+    Page(String),
+    /// `label@PAGEOFF`
+    PageOff(String),
+    Mem(Address),
+    /// `[X17, X10, LSL #3]`, the base plus the index times 8
+    Indexed(String, String),
+    Cond(Cond),
+    /// `LSL #12`, `ASR #63`
+    Shift(&'static str, u32),
+    /// `SXTW`
+    Extend(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub base: String,
+    pub offset: i64,
+    pub mode: AddressMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressMode {
+    /// `[base, #offset]`
+    Offset,
+    /// `[base, #offset]!`
+    PreIndex,
+    /// `[base], #offset`
+    PostIndex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Gt,
+    Le,
+    Lt,
+    Ge,
+    /// Unsigned <, <=, > and >=
+    Lo,
+    Ls,
+    Hi,
+    Hs,
+    /// Signed overflow
+    Vs,
+    Vc,
+    /// Negative, float < without NaN
+    Mi,
+    Pl,
+}
+
+impl Cond {
+    pub fn inverse(self) -> Cond {
+        match self {
+            Cond::Eq => Cond::Ne,
+            Cond::Ne => Cond::Eq,
+            Cond::Gt => Cond::Le,
+            Cond::Le => Cond::Gt,
+            Cond::Lt => Cond::Ge,
+            Cond::Ge => Cond::Lt,
+            Cond::Lo => Cond::Hs,
+            Cond::Hs => Cond::Lo,
+            Cond::Ls => Cond::Hi,
+            Cond::Hi => Cond::Ls,
+            Cond::Vs => Cond::Vc,
+            Cond::Vc => Cond::Vs,
+            Cond::Mi => Cond::Pl,
+            Cond::Pl => Cond::Mi,
+        }
+    }
+}
+
+impl Cond {
+    /// The same comparison on unsigned operands.
+    pub fn unsigned(self) -> Cond {
+        match self {
+            Cond::Gt => Cond::Hi,
+            Cond::Ge => Cond::Hs,
+            Cond::Lt => Cond::Lo,
+            Cond::Le => Cond::Ls,
+            cond => cond,
+        }
+    }
+
+    /// The same comparison after FCMP, false when an operand is NaN.
+    pub fn ordered(self) -> Cond {
+        match self {
+            Cond::Lt => Cond::Mi,
+            Cond::Le => Cond::Ls,
+            cond => cond,
+        }
+    }
+}
+
+pub fn reg(name: &str) -> Operand {
+    Operand::Reg(name.to_owned())
+}
+
+pub fn imm(value: i64) -> Operand {
+    Operand::Imm(value)
+}
+
+pub fn label(name: &str) -> Operand {
+    Operand::Label(name.to_owned())
+}
+
+pub fn mem(base: &str, offset: i64, mode: AddressMode) -> Operand {
+    Operand::Mem(Address { base: base.to_owned(), offset, mode })
+}
+
+impl Instr {
+    pub fn is_code(&self) -> bool {
+        matches!(self, Instr::Op(..) | Instr::BCond(..))
+    }
+}
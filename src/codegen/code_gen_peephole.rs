@@ -0,0 +1,385 @@
+//! Peephole optimizations over the emitted instructions.
+//!
+//! Each rule rewrites a short window of instructions. Every function is swept
+//! front to back, trying each rule at each instruction, until a sweep changes
+//! nothing. Rules that delete a register definition consult a liveness analysis
+//! over the instructions themselves, so the pass does not need anything from
+//! the IR.
+
+use std::collections::HashMap;
+
+use super::code_gen_instr::{label, Address, AddressMode, Cond, Instr, Operand};
+
+type RegSet = u32;
+
+const ALL_REGS: RegSet = u32::MAX >> 1;
+const CALLER_SAVED: RegSet = (1 << 19) - 1;
+const ARGUMENTS: RegSet = 0xff;
+const FRAME: RegSet = 0b11 << 29;
+// X19..X28, FP and LR
+const PRESERVED: RegSet = ALL_REGS & !CALLER_SAVED;
+
+/// Rewrites the instructions up to the first `.data`, `.const` or `.bss` directive,
+/// everything after it is kept as is.
+pub fn optimize(mut code: Vec<Instr>) -> Vec<Instr> {
+    let data = code
+        .iter()
+        .position(|instr| matches!(instr, Instr::Directive(text) if matches!(text.as_str(), ".data" | ".const" | ".bss")))
+        .unwrap_or(code.len());
+    let tail = code.split_off(data);
+
+    // Last first, as a function that does not return falls into the next one
+    // and routines jump to the ones after them
+    let mut funcs = vec![];
+    let mut entries = HashMap::new();
+    let mut live_after = 0;
+    for func in split_functions(code).into_iter().rev() {
+        let (func, live_in) = optimize_func(func, live_after, &entries);
+        if let Some(Instr::Label(name)) = func.first() {
+            entries.insert(name.clone(), live_in);
+        }
+        funcs.push(func);
+        live_after = live_in;
+    }
+    let code: Vec<Instr> = funcs.into_iter().rev().flatten().collect();
+
+    // A routine may end jumping to the one right after it
+    let jumps: Vec<bool> = (0..code.len()).map(|index| jumps_to_next(&code, index)).collect();
+    let mut optimized: Vec<Instr> = code.into_iter().zip(jumps).filter(|(_, jump)| !jump).map(|(instr, _)| instr).collect();
+    optimized.extend(tail);
+    optimized
+}
+
+const RULES: [fn(&mut Sweep, usize) -> bool; 5] = [
+    remove_push_pop,
+    resolve_constant_branch,
+    fold_compare_branch,
+    remove_jump_to_next,
+    remove_dead_moves,
+];
+
+// Functions and runtime routines start at a label with a leading underscore,
+// block labels have none
+fn split_functions(code: Vec<Instr>) -> Vec<Vec<Instr>> {
+    let mut funcs: Vec<Vec<Instr>> = vec![];
+    for instr in code {
+        if funcs.is_empty() || matches!(&instr, Instr::Label(name) if name.starts_with('_')) {
+            funcs.push(vec![]);
+        }
+        funcs.last_mut().unwrap().push(instr);
+    }
+    funcs
+}
+
+// The optimized function and the registers live on entry, `live_after` are
+// live where it falls off the end
+fn optimize_func(mut code: Vec<Instr>, live_after: RegSet, entries: &HashMap<String, RegSet>) -> (Vec<Instr>, RegSet) {
+    loop {
+        let (live, live_in) = liveness(&code, live_after, entries);
+        let mut sweep = Sweep { live, removed: vec![false; code.len()], code };
+        let mut changed = false;
+        let mut index = 0;
+        while index < sweep.code.len() {
+            // A rewrite may let another rule apply at the same place
+            if RULES.iter().any(|rule| rule(&mut sweep, index)) {
+                changed = true;
+            } else {
+                index += 1;
+            }
+        }
+        code = sweep.code.into_iter().zip(sweep.removed).filter(|(_, removed)| !removed).map(|(instr, _)| instr).collect();
+        if !changed {
+            return (code, live_in);
+        }
+    }
+}
+
+/// A function during one sweep. Removed instructions turn into empty comments,
+/// which the rules skip, so indices stay put until the sweep drops them.
+struct Sweep {
+    code: Vec<Instr>,
+    /// Registers live after each instruction. Rewrites only ever shorten live
+    /// ranges, so the liveness computed before the sweep stays a safe superset.
+    live: Vec<RegSet>,
+    removed: Vec<bool>,
+}
+
+impl Sweep {
+    fn remove(&mut self, index: usize) {
+        self.code[index] = Instr::Comment(String::new());
+        self.removed[index] = true;
+    }
+}
+
+// Operands
+
+// X0..X30 and their W views
+fn reg_number(name: &str) -> Option<u32> {
+    let number: u32 = name.strip_prefix(['X', 'W'])?.parse().ok()?;
+    (number <= 30).then_some(number)
+}
+
+fn regs_in(operands: &[Operand]) -> RegSet {
+    operands
+        .iter()
+        .flat_map(|operand| match operand {
+            Operand::Reg(name) => vec![reg_number(name)],
+            Operand::Mem(Address { base, .. }) => vec![reg_number(base)],
+            Operand::Indexed(base, index) => vec![reg_number(base), reg_number(index)],
+            _ => vec![],
+        })
+        .flatten()
+        .fold(0, |set, number| set | 1 << number)
+}
+
+fn reads_flags(instr: &Instr) -> bool {
+    match instr {
+        Instr::BCond(..) => true,
+        Instr::Op(opcode, _) => matches!(*opcode, "CSET" | "CSEL" | "CSINC" | "CINC" | "CCMP" | "CCMN" | "ADC" | "SBC"),
+        _ => false,
+    }
+}
+
+// Liveness
+
+enum Flow<'a> {
+    Next,
+    Jump(&'a str),
+    Branch(&'a str),
+    Exit,
+}
+
+struct Effects<'a> {
+    uses: RegSet,
+    defs: RegSet,
+    flow: Flow<'a>,
+}
+
+fn label_name(operand: &Operand) -> &str {
+    match operand {
+        Operand::Label(name) => name,
+        _ => panic!("Expected a label, found {:?}", operand),
+    }
+}
+
+fn effects(instr: &Instr) -> Effects<'_> {
+    let (opcode, operands) = match instr {
+        Instr::Op(opcode, operands) => (*opcode, operands),
+        Instr::BCond(_, target) => return Effects { uses: 0, defs: 0, flow: Flow::Branch(target) },
+        _ => return Effects { uses: 0, defs: 0, flow: Flow::Next },
+    };
+    let all = regs_in(operands);
+    let (first, rest) = match operands.split_first() {
+        Some((first, rest)) => (regs_in(std::slice::from_ref(first)), regs_in(rest)),
+        None => (0, 0),
+    };
+
+    let (uses, defs, flow) = match opcode {
+        "B" => (0, 0, Flow::Jump(label_name(&operands[0]))),
+        "CBZ" | "CBNZ" => (first, 0, Flow::Branch(label_name(&operands[1]))),
+        "BL" => (ARGUMENTS, CALLER_SAVED | 1 << 30, Flow::Next),
+        "RET" => (1 | PRESERVED, 0, Flow::Exit),
+        "BRK" => (0, 0, Flow::Exit),
+        // Jump table, any block of the function may follow
+        "BR" => (ALL_REGS, 0, Flow::Exit),
+        // read(X0, X1, X2), write(X0, X1, X2) and exit(X0), numbered through X16
+        "SVC" => (0b111 | 1 << 16, 1, Flow::Next),
+        "CMP" | "CMN" | "CCMP" | "CCMN" => (all, 0, Flow::Next),
+        "STR" | "STRB" | "STP" => (all, 0, Flow::Next),
+        "LDP" => (regs_in(&operands[2..]), regs_in(&operands[..2]), Flow::Next),
+        // Keeps the other bits of the register
+        "MOVK" => (all, first, Flow::Next),
+        _ => (rest, first, Flow::Next),
+    };
+    Effects { uses, defs, flow }
+}
+
+/// Registers live after each instruction and on entry, given the ones live
+/// after the last instruction and at the start of the functions that follow.
+fn liveness(code: &[Instr], live_after: RegSet, entries: &HashMap<String, RegSet>) -> (Vec<RegSet>, RegSet) {
+    let labels: HashMap<&str, usize> = code
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instr)| match instr {
+            Instr::Label(name) => Some((name.as_str(), index)),
+            _ => None,
+        })
+        .collect();
+    let effects: Vec<Effects> = code.iter().map(effects).collect();
+
+    let mut live_in = vec![0; code.len() + 1];
+    live_in[code.len()] = live_after;
+    let mut live_out = vec![0; code.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..code.len()).rev() {
+            // Any other label outside of this code could lead anywhere
+            let target = |name: &str| match labels.get(name) {
+                Some(&at) => live_in[at],
+                None => entries.get(name).copied().unwrap_or(ALL_REGS),
+            };
+            let out = match effects[index].flow {
+                Flow::Next => live_in[index + 1],
+                Flow::Jump(name) => target(name),
+                Flow::Branch(name) => target(name) | live_in[index + 1],
+                Flow::Exit => 0,
+            };
+            let input = effects[index].uses | (out & !effects[index].defs);
+            if input != live_in[index] || out != live_out[index] {
+                live_in[index] = input;
+                live_out[index] = out;
+                changed = true;
+            }
+        }
+    }
+    (live_out, live_in[0])
+}
+
+// Rules
+
+// Next instruction after `index`, skipping comments but not labels
+fn next_code(code: &[Instr], index: usize) -> Option<usize> {
+    let next = (index + 1..code.len()).find(|&next| !matches!(code[next], Instr::Comment(_)))?;
+    code[next].is_code().then_some(next)
+}
+
+fn flags_read_after(code: &[Instr], index: usize) -> bool {
+    next_code(code, index).is_some_and(|next| reads_flags(&code[next]))
+}
+
+fn is_stack_access(operand: &Operand, offset: i64, mode: AddressMode) -> bool {
+    *operand == Operand::Mem(Address { base: "SP".to_owned(), offset, mode })
+}
+
+// STP a, b, [SP, #-16]! directly followed by LDP a, b, [SP], #16
+fn remove_push_pop(sweep: &mut Sweep, index: usize) -> bool {
+    let code = &sweep.code;
+    let Some(next) = next_code(code, index) else { return false };
+    let (Instr::Op(push, pushed), Instr::Op(pop, popped)) = (&code[index], &code[next]) else {
+        return false;
+    };
+    let (Some((push_to, pushed)), Some((pop_from, popped))) = (pushed.split_last(), popped.split_last()) else {
+        return false;
+    };
+    if !is_stack_access(push_to, -16, AddressMode::PreIndex) || !is_stack_access(pop_from, 16, AddressMode::PostIndex) {
+        return false;
+    }
+    let replacement = match (*push, *pop) {
+        ("STP", "LDP") | ("STR", "LDR") if pushed == popped => None,
+        ("STR", "LDR") => Some(Instr::Op("MOV", vec![popped[0].clone(), pushed[0].clone()])),
+        _ => return false,
+    };
+    sweep.remove(next);
+    match replacement {
+        // The MOV defines what the LDR did, so it is live out where the LDR was
+        Some(mov) => {
+            sweep.code[index] = mov;
+            sweep.live[index] = sweep.live[next];
+        }
+        None => sweep.remove(index),
+    }
+    true
+}
+
+// MOV Xd, #imm ... CMP Xd, #0; B.EQ label always goes the same way
+fn resolve_constant_branch(sweep: &mut Sweep, index: usize) -> bool {
+    let code = &sweep.code;
+    let Instr::Op("CMP", operands) = &code[index] else { return false };
+    let [Operand::Reg(register), Operand::Imm(0)] = operands.as_slice() else { return false };
+    let Some(register) = reg_number(register) else { return false };
+    let Some(branch) = next_code(code, index) else { return false };
+    let Instr::BCond(cond @ (Cond::Eq | Cond::Ne), target) = &code[branch] else { return false };
+    let Some(value) = constant_before(code, index, register) else { return false };
+    if flags_read_after(code, branch) {
+        return false;
+    }
+
+    if (value == 0) == (*cond == Cond::Eq) {
+        sweep.code[branch] = Instr::Op("B", vec![label(target)]);
+    } else {
+        sweep.remove(branch);
+    }
+    sweep.remove(index);
+    true
+}
+
+// Immediate last moved into `register` within the same block
+fn constant_before(code: &[Instr], index: usize, register: u32) -> Option<i64> {
+    for instr in code[..index].iter().rev() {
+        match instr {
+            Instr::Label(_) | Instr::BCond(..) => return None,
+            Instr::Op(opcode, operands) => {
+                let effects = effects(instr);
+                if !matches!(effects.flow, Flow::Next) || *opcode == "BL" {
+                    return None;
+                }
+                if effects.defs & 1 << register != 0 {
+                    return match (*opcode, operands.as_slice()) {
+                        ("MOV", [_, Operand::Imm(value)]) => Some(*value),
+                        _ => None,
+                    };
+                }
+            }
+            Instr::Comment(_) | Instr::Directive(_) => {}
+        }
+    }
+    None
+}
+
+// CSET Xd, cc; CMP Xd, #0; B.EQ label branches on the flags directly
+fn fold_compare_branch(sweep: &mut Sweep, index: usize) -> bool {
+    let code = &sweep.code;
+    let Instr::Op("CSET", operands) = &code[index] else { return false };
+    let [register @ Operand::Reg(name), Operand::Cond(cond)] = operands.as_slice() else { return false };
+    let Some(cmp) = next_code(code, index) else { return false };
+    if code[cmp] != Instr::Op("CMP", vec![register.clone(), Operand::Imm(0)]) {
+        return false;
+    }
+    let Some(branch) = next_code(code, cmp) else { return false };
+    let Instr::BCond(branch_cond @ (Cond::Eq | Cond::Ne), target) = &code[branch] else { return false };
+    if flags_read_after(code, branch) {
+        return false;
+    }
+
+    // B.EQ takes the branch when the CSET produced 0
+    let cond = if *branch_cond == Cond::Eq { cond.inverse() } else { *cond };
+    let dead = reg_number(name).is_some_and(|number| sweep.live[branch] & 1 << number == 0);
+    sweep.code[branch] = Instr::BCond(cond, target.clone());
+    sweep.remove(cmp);
+    if dead {
+        sweep.remove(index);
+    }
+    true
+}
+
+// B label where label is the next thing executed anyway
+fn remove_jump_to_next(sweep: &mut Sweep, index: usize) -> bool {
+    let jumps = jumps_to_next(&sweep.code, index);
+    if jumps {
+        sweep.remove(index);
+    }
+    jumps
+}
+
+fn jumps_to_next(code: &[Instr], index: usize) -> bool {
+    let Instr::Op("B", operands) = &code[index] else { return false };
+    let target = label_name(&operands[0]);
+    code[index + 1..]
+        .iter()
+        .take_while(|instr| !instr.is_code())
+        .any(|instr| matches!(instr, Instr::Label(name) if name == target))
+}
+
+// MOV to a register nobody reads afterwards
+fn remove_dead_moves(sweep: &mut Sweep, index: usize) -> bool {
+    let Instr::Op("MOV", operands) = &sweep.code[index] else { return false };
+    let defs = regs_in(&operands[..1]);
+    // SP is not tracked, FP and LR are never dead
+    let dead = defs != 0 && defs & FRAME == 0 && sweep.live[index] & defs == 0;
+    let removable = operands[0] == operands[1] || dead;
+    if removable {
+        sweep.remove(index);
+    }
+    removable
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter, Result};
+
+use super::code_gen_instr::{Address, AddressMode, Cond, Instr, Operand};
+
+// Apple assembler syntax, one line per instruction:
+//
+// main_bb0:
+// ;Return stmt
+// MOV X0, #10
+// B.EQ main_bb2
+
+pub fn print(instrs: &[Instr]) -> String {
+    instrs.iter().map(|instr| format!("{}\n", instr)).collect()
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Instr::Op(opcode, operands) => {
+                write!(f, "{}", opcode)?;
+                for (index, operand) in operands.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, operand)?;
+                }
+                Ok(())
+            }
+            Instr::BCond(cond, target) => write!(f, "B.{} {}", cond, target),
+            Instr::Label(name) => write!(f, "{}:", name),
+            Instr::Comment(text) => write!(f, ";{}", text),
+            Instr::Directive(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Operand::Reg(name) => write!(f, "{}", name),
+            Operand::Imm(value) => write!(f, "#{}", value),
+            Operand::Label(name) => write!(f, "{}", name),
+            Operand::Page(name) => write!(f, "{}@PAGE", name),
+            Operand::PageOff(name) => write!(f, "{}@PAGEOFF", name),
+            Operand::Mem(address) => write!(f, "{}", address),
+            Operand::Indexed(base, index) => write!(f, "[{}, {}, LSL #3]", base, index),
+            Operand::Cond(cond) => write!(f, "{}", cond),
+            Operand::Shift(kind, amount) => write!(f, "{} #{}", kind, amount),
+            Operand::Extend(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match (self.mode, self.offset) {
+            (AddressMode::Offset, 0) => write!(f, "[{}]", self.base),
+            (AddressMode::Offset, offset) => write!(f, "[{}, #{}]", self.base, offset),
+            (AddressMode::PreIndex, offset) => write!(f, "[{}, #{}]!", self.base, offset),
+            (AddressMode::PostIndex, offset) => write!(f, "[{}], #{}", self.base, offset),
+        }
+    }
+}
+
+impl Display for Cond {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let name = match self {
+            Cond::Eq => "EQ",
+            Cond::Ne => "NE",
+            Cond::Gt => "GT",
+            Cond::Le => "LE",
+            Cond::Lt => "LT",
+            Cond::Ge => "GE",
+            Cond::Lo => "LO",
+            Cond::Ls => "LS",
+            Cond::Hi => "HI",
+            Cond::Hs => "HS",
+            Cond::Vs => "VS",
+            Cond::Vc => "VC",
+            Cond::Mi => "MI",
+            Cond::Pl => "PL",
+        };
+        write!(f, "{}", name)
+    }
+}
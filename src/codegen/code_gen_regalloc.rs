@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{IrFunction, IrInst, IrStruct, LocalId, Terminator, VReg};
+
+/// Anything that needs a home while the function runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    VReg(VReg),
+    Local(LocalId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Reg(&'static str),
+    /// Index of a stack slot in the function frame
+    Slot(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct Allocation {
+    pub locations: HashMap<Value, Location>,
+    /// Callee saved registers handed out, which the prologue has to preserve
+    pub callee_saved: Vec<&'static str>,
+    pub slot_count: usize,
+}
+
+impl Allocation {
+    pub fn location(&self, value: Value) -> Location {
+        *self
+            .locations
+            .get(&value)
+            .unwrap_or_else(|| panic!("{:?} has no location", value))
+    }
+}
+
+// X9-X11 are kept free as scratch registers for spilled values.
+// X16-X18 are left alone: syscall number, linker veneers and the platform register.
+const CALLER_SAVED: [&str; 4] = ["X12", "X13", "X14", "X15"];
+const CALLEE_SAVED: [&str; 10] = ["X19", "X20", "X21", "X22", "X23", "X24", "X25", "X26", "X27", "X28"];
+
+#[derive(Debug)]
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+fn inst_uses(inst: &IrInst) -> Vec<Value> {
+    match inst {
+        IrInst::Load { local, .. } => vec![Value::Local(*local)],
+        _ => inst.operands().into_iter().map(Value::VReg).collect(),
+    }
+}
+
+fn inst_def(inst: &IrInst) -> Option<Value> {
+    match inst {
+        IrInst::Store { local, .. } => Some(Value::Local(*local)),
+        _ => inst.dst().map(Value::VReg),
+    }
+}
+
+fn terminator_uses(terminator: &Terminator) -> Vec<Value> {
+    terminator.operands().into_iter().map(Value::VReg).collect()
+}
+
+fn successors(terminator: &Terminator) -> Vec<usize> {
+    terminator.successors().into_iter().map(|target| target.0).collect()
+}
+
+// Values live on entry of every block
+fn live_in_sets(func: &IrFunction) -> Vec<HashSet<Value>> {
+    let mut gens = vec![];
+    let mut kills = vec![];
+    for block in func.blocks.iter() {
+        let mut gen = HashSet::new();
+        let mut kill = HashSet::new();
+        for inst in block.insts.iter() {
+            for value in inst_uses(inst) {
+                if !kill.contains(&value) {
+                    gen.insert(value);
+                }
+            }
+            kill.extend(inst_def(inst));
+        }
+        for value in terminator_uses(&block.terminator) {
+            if !kill.contains(&value) {
+                gen.insert(value);
+            }
+        }
+        gens.push(gen);
+        kills.push(kill);
+    }
+
+    let mut live_in: Vec<HashSet<Value>> = vec![HashSet::new(); func.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in func.blocks.iter().enumerate().rev() {
+            let mut live: HashSet<Value> = successors(&block.terminator)
+                .into_iter()
+                .flat_map(|succ| live_in[succ].iter().copied())
+                .filter(|value| !kills[index].contains(value))
+                .collect();
+            live.extend(gens[index].iter().copied());
+            if live.len() != live_in[index].len() {
+                live_in[index] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+fn build_intervals(func: &IrFunction) -> Vec<Interval> {
+    let live_in = live_in_sets(func);
+    let mut ranges: HashMap<Value, (usize, usize)> = HashMap::new();
+    let mut extend = |value: Value, position: usize| {
+        let range = ranges.entry(value).or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+
+    // Instructions are numbered in layout order, the terminator comes last in its block
+    let mut calls = vec![];
+    let mut position = 0;
+    for (index, block) in func.blocks.iter().enumerate() {
+        let block_start = position;
+        for value in live_in[index].iter() {
+            extend(*value, block_start);
+        }
+        for inst in block.insts.iter() {
+            for value in inst_uses(inst) {
+                extend(value, position);
+            }
+            if let Some(value) = inst_def(inst) {
+                extend(value, position);
+            }
+            if inst.is_call() {
+                calls.push(position);
+            }
+            position += 1;
+        }
+        for value in terminator_uses(&block.terminator) {
+            extend(value, position);
+        }
+        for succ in successors(&block.terminator) {
+            for value in live_in[succ].iter() {
+                extend(*value, position);
+            }
+        }
+        position += 1;
+    }
+
+    // Parameters arrive in registers on entry, even the ones only read later
+    for index in 0..func.params {
+        if let Some(range) = ranges.get_mut(&Value::Local(LocalId(index))) {
+            range.0 = 0;
+        }
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(value, (start, end))| Interval {
+            value,
+            start,
+            end,
+            crosses_call: calls.iter().any(|call| start < *call && *call < end),
+        })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.value));
+    intervals
+}
+
+/// Linear scan register allocation. Values alive across a call only get
+/// callee saved registers, everything else prefers caller saved ones. When
+/// registers run out the interval ending last is spilled to a stack slot.
+pub fn allocate(func: &IrFunction, structs: &[IrStruct]) -> Allocation {
+    let mut allocation = reserve_memory(func, structs);
+    let mut free_caller: Vec<&'static str> = CALLER_SAVED.iter().rev().copied().collect();
+    let mut free_callee: Vec<&'static str> = CALLEE_SAVED.iter().rev().copied().collect();
+    // (end, value, register)
+    let mut active: Vec<(usize, Value, &'static str)> = vec![];
+
+    for interval in build_intervals(func) {
+        active.retain(|(end, _, reg)| {
+            if *end < interval.start {
+                if CALLER_SAVED.contains(reg) {
+                    free_caller.push(reg);
+                } else {
+                    free_callee.push(reg);
+                }
+                false
+            } else {
+                true
+            }
+        });
+
+        let reg = if interval.crosses_call {
+            free_callee.pop()
+        } else {
+            free_caller.pop().or_else(|| free_callee.pop())
+        };
+
+        if let Some(reg) = reg {
+            if CALLEE_SAVED.contains(&reg) && !allocation.callee_saved.contains(&reg) {
+                allocation.callee_saved.push(reg);
+            }
+            allocation.locations.insert(interval.value, Location::Reg(reg));
+            active.push((interval.end, interval.value, reg));
+            continue;
+        }
+
+        // Steal the register of the active interval that lives the longest
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, reg))| !interval.crosses_call || CALLEE_SAVED.contains(reg))
+            .max_by_key(|(_, (end, _, _))| *end)
+            .map(|(index, _)| index);
+
+        match victim {
+            Some(index) if active[index].0 > interval.end => {
+                let (_, victim_value, reg) = active.remove(index);
+                allocation.locations.insert(victim_value, Location::Slot(allocation.slot_count));
+                allocation.slot_count += 1;
+                allocation.locations.insert(interval.value, Location::Reg(reg));
+                active.push((interval.end, interval.value, reg));
+            }
+            _ => {
+                allocation.locations.insert(interval.value, Location::Slot(allocation.slot_count));
+                allocation.slot_count += 1;
+            }
+        }
+    }
+
+    allocation.callee_saved.sort();
+    allocation
+}
+
+/// Allocation for unoptimized builds, every value gets a stack slot of its own
+/// and only visits a scratch register while an instruction uses it.
+pub fn allocate_on_stack(func: &IrFunction, structs: &[IrStruct]) -> Allocation {
+    let mut allocation = reserve_memory(func, structs);
+    for interval in build_intervals(func) {
+        allocation.locations.insert(interval.value, Location::Slot(allocation.slot_count));
+        allocation.slot_count += 1;
+    }
+    allocation
+}
+
+// Arrays are indexed at runtime and structs are reached through their
+// address, so both always live in memory. An array gets one slot per element,
+// a struct as many as its size needs. The location is the first slot.
+fn reserve_memory(func: &IrFunction, structs: &[IrStruct]) -> Allocation {
+    let mut allocation = Allocation::default();
+    for (index, local) in func.locals.iter().enumerate() {
+        if let Some(slots) = local.slots(structs) {
+            allocation.locations.insert(Value::Local(LocalId(index)), Location::Slot(allocation.slot_count));
+            // An empty array or struct still gets an address
+            allocation.slot_count += slots.max(1);
+        }
+    }
+    allocation
+}
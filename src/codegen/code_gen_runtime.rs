@@ -0,0 +1,321 @@
+//! Routines the generated code calls into, emitted once after the functions.
+//!
+//! They follow the usual calling convention: arguments and results in X0 and
+//! X1, and callee saved registers are restored before returning.
+
+use crate::ir::{IrType, VReg};
+
+use super::{
+    code_gen_checks::TRAP_EXIT_CODE,
+    code_gen_instr::{imm, label, mem, reg, AddressMode, Cond, Instr, Operand},
+    code_gen_regalloc::Value,
+    code_gen_stack::SCRATCH_LHS,
+    code_gen_structs::{w_reg, Generator},
+};
+
+const PRINT_STR: &str = "_bk_print_str";
+const PRINT_BOOL: &str = "_bk_print_bool";
+const PRINT_INT: &str = "_bk_print_int";
+const NEXT_BYTE: &str = "_bk_next_byte";
+const READ_INT: &str = "_bk_read_int";
+const READ_LINE: &str = "_bk_read_line";
+
+/// Buffered stdin: the read position, the number of bytes read, then the bytes.
+const INPUT: &str = "_bk_input";
+const INPUT_SIZE: i64 = 4096;
+/// Lines returned by `read_line`, stored one after the other like string
+/// literals after the number of bytes in use. They are never freed.
+const LINES: &str = "_bk_lines";
+const LINES_SIZE: i64 = 1 << 20;
+
+/// Strings the runtime prints, laid out like string literals.
+pub const PRINT_STRINGS: [(&str, &str); 3] = [("_bk_true", "true"), ("_bk_false", "false"), ("_bk_newline", "\n")];
+pub const READ_STRINGS: [(&str, &str); 1] = [("_bk_lines_full", "read_line ran out of memory\n")];
+
+impl Generator {
+    pub fn gen_print(&mut self, src: VReg, newline: bool) {
+        let ty = self.m_vreg_types[src.0];
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        self.emit("MOV", vec![reg("X0"), reg(src)]);
+        match ty {
+            IrType::Str => self.emit("BL", vec![label(PRINT_STR)]),
+            IrType::Bool => self.emit("BL", vec![label(PRINT_BOOL)]),
+            _ => {
+                // Narrow values are already extended to 64 bits
+                self.emit("MOV", vec![reg("X1"), imm(ty.is_signed() as i64)]);
+                self.emit("BL", vec![label(PRINT_INT)]);
+            }
+        }
+        if newline {
+            self.load_address("X0", "_bk_newline");
+            self.emit("BL", vec![label(PRINT_STR)]);
+        }
+        self.uses_print = true;
+    }
+
+    pub fn gen_read(&mut self, dst: VReg) {
+        let routine = match self.m_vreg_types[dst.0] {
+            IrType::Str => READ_LINE,
+            _ => READ_INT,
+        };
+        self.emit("BL", vec![label(routine)]);
+        self.move_into(Value::VReg(dst), "X0");
+        self.uses_read = true;
+    }
+
+    fn load_address(&mut self, register: &str, name: &str) {
+        self.emit("ADRP", vec![reg(register), Operand::Page(name.to_owned())]);
+        self.emit("ADD", vec![reg(register), reg(register), Operand::PageOff(name.to_owned())]);
+    }
+
+    pub fn gen_runtime(&mut self) {
+        if self.uses_print {
+            self.gen_print_routines();
+        }
+        if self.uses_read {
+            self.gen_next_byte();
+            self.gen_read_int();
+            self.gen_read_line();
+        }
+    }
+
+    /// Zero initialized memory of the read routines, emitted after every
+    /// other section.
+    pub fn gen_runtime_data(&mut self) {
+        if !self.uses_read {
+            return;
+        }
+        self.directive(".bss");
+        self.directive(".p2align 3");
+        self.label(INPUT);
+        self.directive(&format!(".space {}", 16 + INPUT_SIZE));
+        self.label(LINES);
+        // Room for the header of a line that starts right at the end
+        self.directive(&format!(".space {}", 8 + LINES_SIZE + 16));
+    }
+
+    fn gen_print_routines(&mut self) {
+        self.comment("write(stdout) the str at X0");
+        self.label(PRINT_STR);
+        self.emit("LDR", vec![reg("X2"), mem("X0", 0, AddressMode::Offset)]);
+        self.emit("ADD", vec![reg("X1"), reg("X0"), imm(8)]);
+        self.gen_write();
+        self.emit("RET", vec![]);
+
+        self.comment("Prints true when X0 is non zero, false otherwise");
+        self.label(PRINT_BOOL);
+        self.load_address("X1", "_bk_true");
+        self.load_address("X2", "_bk_false");
+        self.emit("CMP", vec![reg("X0"), imm(0)]);
+        self.emit("CSEL", vec![reg("X0"), reg("X1"), reg("X2"), Operand::Cond(Cond::Ne)]);
+        self.emit("B", vec![label(PRINT_STR)]);
+
+        self.gen_print_int();
+    }
+
+    // Returns the next byte of stdin in X0, -1 at the end of the input.
+    // Uses X0..X2, X16 and X17 only.
+    fn gen_next_byte(&mut self) {
+        let (position, length, base) = ("X1", "X2", "X17");
+        let ready = format!("{}_ready", NEXT_BYTE);
+        let end = format!("{}_end", NEXT_BYTE);
+
+        self.comment("Returns the next byte of stdin, -1 at the end");
+        self.label(NEXT_BYTE);
+        self.load_address(base, INPUT);
+        self.emit("LDR", vec![reg(position), mem(base, 0, AddressMode::Offset)]);
+        self.emit("LDR", vec![reg(length), mem(base, 8, AddressMode::Offset)]);
+        self.emit("CMP", vec![reg(position), reg(length)]);
+        self.buffer.push(Instr::BCond(Cond::Lt, ready.clone()));
+
+        // read(0, bytes, INPUT_SIZE), which fails with the carry set
+        self.emit("MOV", vec![reg("X0"), imm(0)]);
+        self.emit("ADD", vec![reg("X1"), reg(base), imm(16)]);
+        self.emit("MOV", vec![reg("X2"), imm(INPUT_SIZE)]);
+        self.emit("MOV", vec![reg("X16"), imm(3)]);
+        self.emit("SVC", vec![imm(0x80)]);
+        self.buffer.push(Instr::BCond(Cond::Hs, end.clone()));
+        self.emit("CMP", vec![reg("X0"), imm(0)]);
+        self.buffer.push(Instr::BCond(Cond::Le, end.clone()));
+        self.load_address(base, INPUT);
+        self.emit("STR", vec![reg("X0"), mem(base, 8, AddressMode::Offset)]);
+        self.emit("MOV", vec![reg(position), imm(0)]);
+
+        self.label(&ready);
+        self.emit("ADD", vec![reg(length), reg(base), imm(16)]);
+        self.emit("ADD", vec![reg(length), reg(length), reg(position)]);
+        self.emit("LDRB", vec![reg("W0"), mem(length, 0, AddressMode::Offset)]);
+        self.emit("ADD", vec![reg(position), reg(position), imm(1)]);
+        self.emit("STR", vec![reg(position), mem(base, 0, AddressMode::Offset)]);
+        self.emit("RET", vec![]);
+
+        self.label(&end);
+        self.emit("MOV", vec![reg("X0"), imm(-1)]);
+        self.emit("RET", vec![]);
+    }
+
+    // Skips white space and parses an optionally negative decimal number,
+    // consuming the byte after it. Anything else reads as 0 and overflow wraps.
+    fn gen_read_int(&mut self) {
+        // Calls to the next byte clobber the caller saved registers
+        let (value, negative, digit, ten) = ("X19", "X20", "X11", "X12");
+        let skip = format!("{}_skip", READ_INT);
+        let next = format!("{}_next", READ_INT);
+        let check = format!("{}_digit", READ_INT);
+        let done = format!("{}_done", READ_INT);
+
+        self.comment("Reads an i64 from stdin");
+        self.label(READ_INT);
+        self.emit("STP", vec![reg("X29"), reg("X30"), mem("SP", -32, AddressMode::PreIndex)]);
+        self.emit("STP", vec![reg(value), reg(negative), mem("SP", 16, AddressMode::Offset)]);
+        self.emit("MOV", vec![reg(value), imm(0)]);
+        self.emit("MOV", vec![reg(negative), imm(0)]);
+
+        self.label(&skip);
+        self.emit("BL", vec![label(NEXT_BYTE)]);
+        self.emit("CMN", vec![reg("X0"), imm(1)]);
+        self.buffer.push(Instr::BCond(Cond::Eq, done.clone()));
+        self.emit("CMP", vec![reg("X0"), imm(b' ' as i64)]);
+        self.buffer.push(Instr::BCond(Cond::Le, skip));
+        self.emit("CMP", vec![reg("X0"), imm(b'-' as i64)]);
+        self.buffer.push(Instr::BCond(Cond::Ne, check.clone()));
+        self.emit("MOV", vec![reg(negative), imm(1)]);
+
+        self.label(&next);
+        self.emit("BL", vec![label(NEXT_BYTE)]);
+        self.label(&check);
+        // The end of the input wraps around past '9' as well
+        self.emit("SUB", vec![reg(digit), reg("X0"), imm(b'0' as i64)]);
+        self.emit("CMP", vec![reg(digit), imm(9)]);
+        self.buffer.push(Instr::BCond(Cond::Hi, done.clone()));
+        self.emit("MOV", vec![reg(ten), imm(10)]);
+        self.emit("MADD", vec![reg(value), reg(value), reg(ten), reg(digit)]);
+        self.emit("B", vec![label(&next)]);
+
+        self.label(&done);
+        self.emit("NEG", vec![reg(digit), reg(value)]);
+        self.emit("CMP", vec![reg(negative), imm(0)]);
+        self.emit("CSEL", vec![reg("X0"), reg(digit), reg(value), Operand::Cond(Cond::Ne)]);
+        self.emit("LDP", vec![reg(value), reg(negative), mem("SP", 16, AddressMode::Offset)]);
+        self.emit("LDP", vec![reg("X29"), reg("X30"), mem("SP", 32, AddressMode::PostIndex)]);
+        self.emit("RET", vec![]);
+    }
+
+    // Copies stdin up to the next new line into a new str and returns its
+    // address. The new line is dropped, the end of the input gives "".
+    fn gen_read_line(&mut self) {
+        let (record, end, limit) = ("X19", "X20", "X21");
+        let next = format!("{}_next", READ_LINE);
+        let done = format!("{}_done", READ_LINE);
+        let full = format!("{}_full", READ_LINE);
+
+        self.comment("Reads a line from stdin as a str");
+        self.label(READ_LINE);
+        self.emit("STP", vec![reg("X29"), reg("X30"), mem("SP", -48, AddressMode::PreIndex)]);
+        self.emit("STP", vec![reg(record), reg(end), mem("SP", 16, AddressMode::Offset)]);
+        self.emit("STR", vec![reg(limit), mem("SP", 32, AddressMode::Offset)]);
+        self.load_address(record, LINES);
+        self.emit("MOV", vec![reg(limit), imm(LINES_SIZE)]);
+        self.emit("ADD", vec![reg(limit), reg(record), reg(limit)]);
+        self.emit("LDR", vec![reg(end), mem(record, 0, AddressMode::Offset)]);
+        self.emit("ADD", vec![reg(record), reg(record), reg(end)]);
+        self.emit("ADD", vec![reg(record), reg(record), imm(8)]);
+        self.emit("ADD", vec![reg(end), reg(record), imm(8)]);
+
+        self.label(&next);
+        self.emit("BL", vec![label(NEXT_BYTE)]);
+        self.emit("CMN", vec![reg("X0"), imm(1)]);
+        self.buffer.push(Instr::BCond(Cond::Eq, done.clone()));
+        self.emit("CMP", vec![reg("X0"), imm(b'\n' as i64)]);
+        self.buffer.push(Instr::BCond(Cond::Eq, done.clone()));
+        self.emit("CMP", vec![reg(end), reg(limit)]);
+        self.buffer.push(Instr::BCond(Cond::Hs, full.clone()));
+        self.emit("STRB", vec![reg("W0"), mem(end, 1, AddressMode::PostIndex)]);
+        self.emit("B", vec![label(&next)]);
+
+        self.label(&done);
+        self.emit("SUB", vec![reg("X0"), reg(end), reg(record)]);
+        self.emit("SUB", vec![reg("X0"), reg("X0"), imm(8)]);
+        self.emit("STR", vec![reg("X0"), mem(record, 0, AddressMode::Offset)]);
+        // The next line starts at the following multiple of 8
+        self.emit("ADD", vec![reg(end), reg(end), imm(7)]);
+        self.emit("AND", vec![reg(end), reg(end), imm(!7)]);
+        self.load_address("X1", LINES);
+        self.emit("SUB", vec![reg(end), reg(end), reg("X1")]);
+        self.emit("SUB", vec![reg(end), reg(end), imm(8)]);
+        self.emit("STR", vec![reg(end), mem("X1", 0, AddressMode::Offset)]);
+        self.emit("MOV", vec![reg("X0"), reg(record)]);
+        self.emit("LDR", vec![reg(limit), mem("SP", 32, AddressMode::Offset)]);
+        self.emit("LDP", vec![reg(record), reg(end), mem("SP", 16, AddressMode::Offset)]);
+        self.emit("LDP", vec![reg("X29"), reg("X30"), mem("SP", 48, AddressMode::PostIndex)]);
+        self.emit("RET", vec![]);
+
+        self.label(&full);
+        self.load_address("X0", "_bk_lines_full");
+        self.emit("LDR", vec![reg("X2"), mem("X0", 0, AddressMode::Offset)]);
+        self.emit("ADD", vec![reg("X1"), reg("X0"), imm(8)]);
+        self.emit("MOV", vec![reg("X0"), imm(2)]);
+        self.emit("MOV", vec![reg("X16"), imm(4)]);
+        self.emit("SVC", vec![imm(0x80)]);
+        self.emit("MOV", vec![reg("X0"), imm(TRAP_EXIT_CODE as i64)]);
+        self.emit("MOV", vec![reg("X16"), imm(1)]);
+        self.emit("SVC", vec![imm(0x80)]);
+    }
+
+    // write(1, X1, X2)
+    fn gen_write(&mut self) {
+        self.emit("MOV", vec![reg("X0"), imm(1)]);
+        self.emit("MOV", vec![reg("X16"), imm(4)]);
+        self.emit("SVC", vec![imm(0x80)]);
+    }
+
+    // Prints X0 in decimal, as a signed number when X1 is 1. Digits are
+    // written backwards from the end of a buffer on the stack, the 20 digits
+    // of u64::MAX and a sign fit in 32 bytes.
+    fn gen_print_int(&mut self) {
+        let (end, value, negative, ten, quotient, digit) = ("X9", "X10", "X11", "X12", "X13", "X14");
+        let digits = format!("{}_digits", PRINT_INT);
+        let write = format!("{}_write", PRINT_INT);
+
+        self.comment("Prints X0 in decimal, signed when X1 is 1");
+        self.label(PRINT_INT);
+        self.emit("SUB", vec![reg("SP"), reg("SP"), imm(32)]);
+        self.emit("ADD", vec![reg(end), reg("SP"), imm(32)]);
+        self.emit("MOV", vec![reg(value), reg("X0")]);
+        self.emit("MOV", vec![reg(negative), imm(0)]);
+        self.emit("CBZ", vec![reg("X1"), label(&digits)]);
+        self.emit("CMP", vec![reg("X0"), imm(0)]);
+        self.buffer.push(Instr::BCond(Cond::Ge, digits.clone()));
+        // i64::MIN negates to itself, which read unsigned is its magnitude
+        self.emit("NEG", vec![reg(value), reg("X0")]);
+        self.emit("MOV", vec![reg(negative), imm(1)]);
+
+        self.label(&digits);
+        self.emit("MOV", vec![reg(ten), imm(10)]);
+        let next_digit = format!("{}_next", PRINT_INT);
+        self.label(&next_digit);
+        self.emit("UDIV", vec![reg(quotient), reg(value), reg(ten)]);
+        self.emit("MSUB", vec![reg(digit), reg(quotient), reg(ten), reg(value)]);
+        self.emit("ADD", vec![reg(digit), reg(digit), imm(b'0' as i64)]);
+        self.push_byte(end, digit);
+        self.emit("MOV", vec![reg(value), reg(quotient)]);
+        self.emit("CBNZ", vec![reg(value), label(&next_digit)]);
+
+        self.emit("CBZ", vec![reg(negative), label(&write)]);
+        self.emit("MOV", vec![reg(digit), imm(b'-' as i64)]);
+        self.push_byte(end, digit);
+
+        self.label(&write);
+        self.emit("MOV", vec![reg("X1"), reg(end)]);
+        self.emit("ADD", vec![reg("X2"), reg("SP"), imm(32)]);
+        self.emit("SUB", vec![reg("X2"), reg("X2"), reg(end)]);
+        self.gen_write();
+        self.emit("ADD", vec![reg("SP"), reg("SP"), imm(32)]);
+        self.emit("RET", vec![]);
+    }
+
+    // Stores the low byte of `byte` just below `end` and moves `end` down to it
+    fn push_byte(&mut self, end: &str, byte: &str) {
+        self.emit("STRB", vec![reg(&w_reg(byte)), mem(end, -1, AddressMode::PreIndex)]);
+    }
+}
@@ -1,33 +1,60 @@
+use super::{
+    code_gen_instr::{reg, Operand},
+    code_gen_regalloc::{Location, Value},
+    code_gen_structs::Generator,
+};
 
-use super::code_gen_structs::Generator;
-
+// Scratch registers for values that were spilled to the stack
+pub const SCRATCH_LHS: &str = "X9";
+pub const SCRATCH_RHS: &str = "X10";
+pub const SCRATCH_DST: &str = "X11";
+// Short lived scratch: the address of a far slot for a single load or store,
+// so it is free again once the operands are loaded. Also holds an array base
+// and the upper half of a checked product.
+pub const SCRATCH_TEMP: &str = "X17";
 
 impl Generator {
-    // Does both store and moving SP 
-    pub fn push(&mut self, value: &str) {
-        self.buffer.push(format!("STP {}, X9, [SP, #-16]!\n", value));
-        self.increase_stack_pointer();
+    // Slots are addressed from FP, see [`super::code_gen_frame::FrameLayout`]
+    fn slot_address(&mut self, slot: usize) -> Operand {
+        self.fp_address(self.m_frame.slot_offset(slot))
     }
 
-    pub fn pop(&mut self, register: &str) {
-        self.buffer.push(format!("LDP {}, X9, [SP], #16\n", register));
-        self.decrease_stack_pointer();
+    /// Register holding `value`, loading it into `scratch` when it was spilled.
+    pub fn use_reg(&mut self, value: Value, scratch: &'static str) -> &'static str {
+        match self.m_alloc.location(value) {
+            Location::Reg(reg) => reg,
+            Location::Slot(slot) => {
+                let address = self.slot_address(slot);
+                self.emit("LDR", vec![reg(scratch), address]);
+                scratch
+            }
+        }
+    }
+
+    /// Register to compute `value` into, finish with [`Generator::finish_def`].
+    pub fn def_reg(&self, value: Value) -> &'static str {
+        match self.m_alloc.location(value) {
+            Location::Reg(reg) => reg,
+            Location::Slot(_) => SCRATCH_DST,
+        }
     }
 
-    pub fn clear_stack(&mut self, offset: usize) {
-        let lhs_pos = offset * 16;
-        self.buffer_push(&format!("ADD SP, SP, #{}", lhs_pos));
-        for _ in 0..offset {
-            self.decrease_stack_pointer();
+    pub fn finish_def(&mut self, value: Value, register: &str) {
+        if let Location::Slot(slot) = self.m_alloc.location(value) {
+            let address = self.slot_address(slot);
+            self.emit("STR", vec![reg(register), address]);
         }
     }
 
-    pub fn pop_with_offset(&mut self, register: &str, offset: usize) {
-        let lhs_pos = offset * 16;
-        self.buffer_push(&format!("LDP {}, X9, [SP, #{}]", register, lhs_pos));
-        self.buffer_push(&format!("ADD SP, SP, #{}", lhs_pos));
-        for _ in 0..offset {
-            self.decrease_stack_pointer();
+    // Copies `register` into wherever `value` lives
+    pub fn move_into(&mut self, value: Value, register: &str) {
+        match self.m_alloc.location(value) {
+            Location::Reg(dst) if dst == register => {}
+            Location::Reg(dst) => self.emit("MOV", vec![reg(dst), reg(register)]),
+            Location::Slot(slot) => {
+                let address = self.slot_address(slot);
+                self.emit("STR", vec![reg(register), address]);
+            }
         }
     }
 }
@@ -0,0 +1,112 @@
+use crate::ir::{IrType, LocalId, VReg};
+
+use super::{
+    code_gen_expr::global_label,
+    code_gen_instr::{imm, mem, reg, AddressMode, Operand},
+    code_gen_regalloc::Value,
+    code_gen_stack::{SCRATCH_DST, SCRATCH_LHS, SCRATCH_RHS},
+    code_gen_structs::{w_reg, Generator},
+};
+
+impl Generator {
+    pub fn gen_addr(&mut self, dst: VReg, local: LocalId) {
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        self.memory_address(dst_reg, local);
+        self.finish_def(dst, dst_reg);
+    }
+
+    pub fn gen_field_addr(&mut self, dst: VReg, base: VReg, offset: usize) {
+        let base = self.use_reg(Value::VReg(base), SCRATCH_LHS);
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        self.add_immediate(dst_reg, base, offset);
+        self.finish_def(dst, dst_reg);
+    }
+
+    pub fn gen_load_field(&mut self, dst: VReg, base: VReg, offset: usize) {
+        let ty = self.m_vreg_types[dst.0];
+        let base = self.use_reg(Value::VReg(base), SCRATCH_LHS);
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        self.load_bytes(dst_reg, field(base, offset), ty);
+        self.finish_def(dst, dst_reg);
+    }
+
+    pub fn gen_store_field(&mut self, base: VReg, offset: usize, src: VReg) {
+        let ty = self.m_vreg_types[src.0];
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        let base = self.use_reg(Value::VReg(base), SCRATCH_RHS);
+        self.store_bytes(src, field(base, offset), ty.bits() / 8);
+    }
+
+    pub fn gen_load_global(&mut self, dst: VReg, global: usize) {
+        let ty = self.m_vreg_types[dst.0];
+        let dst = Value::VReg(dst);
+        let dst_reg = self.def_reg(dst);
+        self.global_address(dst_reg, global);
+        self.load_bytes(dst_reg, field(dst_reg, 0), ty);
+        self.finish_def(dst, dst_reg);
+    }
+
+    pub fn gen_store_global(&mut self, global: usize, src: VReg) {
+        let ty = self.m_vreg_types[src.0];
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        self.global_address(SCRATCH_RHS, global);
+        self.store_bytes(src, field(SCRATCH_RHS, 0), ty.bits() / 8);
+    }
+
+    // Statics are too far away for an offset, their page is added first
+    fn global_address(&mut self, dst: &str, global: usize) {
+        let name = global_label(global);
+        self.emit("ADRP", vec![reg(dst), Operand::Page(name.clone())]);
+        self.emit("ADD", vec![reg(dst), reg(dst), Operand::PageOff(name)]);
+    }
+
+    // Loads sign or zero extend the value to the whole register
+    fn load_bytes(&mut self, dst: &str, address: Operand, ty: IrType) {
+        match ty {
+            IrType::I32 => self.emit("LDRSW", vec![reg(dst), address]),
+            IrType::U32 | IrType::Char => self.emit("LDR", vec![reg(&w_reg(dst)), address]),
+            IrType::U8 | IrType::Bool => self.emit("LDRB", vec![reg(&w_reg(dst)), address]),
+            _ => self.emit("LDR", vec![reg(dst), address]),
+        }
+    }
+
+    // Moves 8 bytes at a time while it can, then 4, then single bytes
+    pub fn gen_copy(&mut self, dst: VReg, src: VReg, size: usize) {
+        let src = self.use_reg(Value::VReg(src), SCRATCH_LHS);
+        let dst = self.use_reg(Value::VReg(dst), SCRATCH_RHS);
+        let mut offset = 0;
+        for width in [8, 4, 1] {
+            while size - offset >= width {
+                let value = match width {
+                    8 => SCRATCH_DST.to_owned(),
+                    _ => w_reg(SCRATCH_DST),
+                };
+                let load = if width == 1 { "LDRB" } else { "LDR" };
+                self.emit(load, vec![reg(&value), field(src, offset)]);
+                self.store_bytes(SCRATCH_DST, field(dst, offset), width as u32);
+                offset += width;
+            }
+        }
+    }
+
+    fn store_bytes(&mut self, src: &str, address: Operand, bytes: u32) {
+        match bytes {
+            1 => self.emit("STRB", vec![reg(&w_reg(src)), address]),
+            4 => self.emit("STR", vec![reg(&w_reg(src)), address]),
+            _ => self.emit("STR", vec![reg(src), address]),
+        }
+    }
+
+    fn add_immediate(&mut self, dst: &str, src: &str, value: usize) {
+        assert!(value < 1 << 12, "Struct of {} bytes is too large", value);
+        self.emit("ADD", vec![reg(dst), reg(src), imm(value as i64)]);
+    }
+}
+
+// Unsigned offsets reach 4095 times the access size, struct fields stay well below
+fn field(base: &str, offset: usize) -> Operand {
+    mem(base, offset as i64, AddressMode::Offset)
+}
@@ -1,149 +1,90 @@
-use std::{collections::HashMap, fs::File, io::Write};
+use crate::ir::{BlockId, IrFunction, IrLocal, IrStruct, IrType};
 
-use tracing::debug;
-
-#[derive(Debug)]
-pub struct Var {
-    pub stack_location: usize,
-    pub line_number: i32,
-}
+use super::{
+    code_gen_frame::FrameLayout,
+    code_gen_instr::{Instr, Operand},
+    code_gen_printer,
+    code_gen_regalloc::Allocation,
+};
 
 #[derive(Debug)]
 pub struct Generator {
-    pub buffer: Vec<String>,
-    pub output: File,
-    pub label_index: usize,
-    pub fn_scope_map: HashMap<String, LocalScopes>,
+    pub buffer: Vec<Instr>,
+    pub runtime_checks: bool,
+    /// Whether the print routines of the runtime are needed
+    pub uses_print: bool,
+    /// Whether the read routines of the runtime are needed
+    pub uses_read: bool,
+    /// Layout of every struct of the module
+    pub structs: Vec<IrStruct>,
     pub m_func_name: String,
+    /// Type of every virtual register of the current function
+    pub m_vreg_types: Vec<IrType>,
+    pub m_locals: Vec<IrLocal>,
+    /// Labels made up inside the current function so far
+    pub m_label_count: usize,
+    pub m_alloc: Allocation,
+    pub m_frame: FrameLayout,
 }
 
 impl Generator {
-    pub fn new() -> Generator {
-        let output = File::create("out.s").expect("Failed to create file");
-        let m_stack_pointer: usize = 0;
-        let fn_scopes = HashMap::new();
+    pub fn new(runtime_checks: bool) -> Generator {
         Generator {
             buffer: vec![],
-            output,
-            label_index: 0,
-            fn_scope_map: fn_scopes,
+            runtime_checks,
+            uses_print: false,
+            uses_read: false,
+            structs: vec![],
             m_func_name: "".to_owned(),
+            m_vreg_types: vec![],
+            m_locals: vec![],
+            m_label_count: 0,
+            m_alloc: Allocation::default(),
+            m_frame: FrameLayout::default(),
         }
     }
 
     pub fn comment(&mut self, comment: &str) {
-        self.buffer.push(format!(";{}\n", comment));
+        self.buffer.push(Instr::Comment(comment.to_owned()));
     }
 
-    pub fn finalize(mut self) {
-        let buf: String = self.buffer.into_iter().collect();
-        let buf = buf.as_bytes();
-
-        let _ = self.output.write_all(buf);
+    pub fn finalize(self) -> String {
+        code_gen_printer::print(&self.buffer)
     }
 
-    pub fn get_variable(&self, identifier: &String) -> Option<&Var> {
-        let local_scopes = self.fn_scope_map.get(&self.m_func_name)?;
-        local_scopes.get_variable(identifier)
+    pub fn begin_func(&mut self, func: &IrFunction, alloc: Allocation) {
+        self.m_func_name = func.name.clone();
+        self.m_vreg_types = func.vregs.clone();
+        self.m_locals = func.locals.clone();
+        self.m_label_count = 0;
+        self.m_frame = FrameLayout::new(&alloc);
+        self.m_alloc = alloc;
     }
 
-    pub fn begin_func(&mut self, c_func_name: String) {
-        self.m_func_name = c_func_name;
+    pub fn block_label(&self, block: BlockId) -> String {
+        format!("{}_bb{}", self.m_func_name, block.0)
     }
 
-    pub fn end_func(&mut self) {
-        let local_scope = self.fn_scope_map.get(&self.m_func_name);
-        if let Some(local_scope) = local_scope {
-            let ident_scope_count = local_scope.m_stack_pointer;
-            // Because of 128-bit alignment in Aarch64 we are pushing dummy values
-            // all the time when we add data to stack so we have use 16 than 8
-            let ident_scope_size = ident_scope_count * 16;
-
-            // Remove scope variables from Stack
-            self.buffer_push(&format!("ADD SP, SP, #{}", ident_scope_size));
-        }
+    /// Fresh label for a jump within a single instruction's code.
+    pub fn local_label(&mut self, name: &str) -> String {
+        self.m_label_count += 1;
+        format!("{}_{}{}", self.m_func_name, name, self.m_label_count)
     }
 
-    pub fn insert_ident(&mut self, identifier: &String, line_number: i32) {
-        let e = self.fn_scope_map.entry(self.m_func_name.to_string());
-        let local_scopes = e.or_insert_with(|| LocalScopes::new());
-        local_scopes.put_identifier(identifier, line_number, local_scopes.m_stack_pointer);
+    pub fn emit(&mut self, opcode: &'static str, operands: Vec<Operand>) {
+        self.buffer.push(Instr::Op(opcode, operands));
     }
 
-    pub fn begin_scope(&self) {}
-
-    pub fn end_scope(&self) {}
-
-    pub fn buffer_push(&mut self, value: &str) {
-        self.buffer.push(format!("{}\n", value));
-    }
-
-    pub fn increase_stack_pointer(&mut self) {
-        let e = self.fn_scope_map.entry(self.m_func_name.to_string());
-        let local_scopes = e.or_insert_with(|| LocalScopes::new());
-        local_scopes.m_stack_pointer += 1;
-
-        let pointer = local_scopes.m_stack_pointer;
-        self.comment(&format!("Compiler mStackPointer {}", pointer));
+    pub fn label(&mut self, name: &str) {
+        self.buffer.push(Instr::Label(name.to_owned()));
     }
 
-    pub fn decrease_stack_pointer(&mut self) {
-        let e = self.fn_scope_map.entry(self.m_func_name.to_string());
-        let local_scopes = e.or_insert_with(|| LocalScopes::new());
-        local_scopes.m_stack_pointer -= 1;
-
-        let pointer = local_scopes.m_stack_pointer;
-        self.comment(&format!("Compiler mStackPointer {}", pointer));
+    pub fn directive(&mut self, text: &str) {
+        self.buffer.push(Instr::Directive(text.to_owned()));
     }
-
-    pub fn current_stack_pointer(&self) -> usize {
-        let local_scope = self.fn_scope_map.get(&self.m_func_name);
-        if let Some(local_scope) = local_scope {
-            return local_scope.m_stack_pointer;
-        }
-        return 0;
-    }
-}
-
-#[derive(Debug)]
-pub struct LocalScopes {
-    pub scope: HashMap<usize, HashMap<String, Var>>,
-    pub index: usize,
-    pub m_stack_pointer: usize,
 }
 
-impl LocalScopes {
-    fn new() -> LocalScopes {
-        let scope_map: HashMap<usize, HashMap<String, Var>> = HashMap::new();
-        LocalScopes {
-            scope: scope_map,
-            index: 0,
-            m_stack_pointer: 0,
-        }
-    }
-
-    fn get_variable(&self, identifier: &String) -> Option<&Var> {
-        for i in (0..=self.index).rev() {
-            if let Some(map) = self.scope.get(&i) {
-                if let Some(var) = map.get(identifier) {
-                    return Some(var);
-                }
-            }
-        }
-        None
-    }
-
-    fn put_identifier(&mut self, identifier: &String, line_number: i32, m_stack_pointer: usize) {
-        let map = self
-            .scope
-            .entry(self.index)
-            .or_insert_with(|| HashMap::new());
-        map.insert(
-            identifier.to_string(),
-            Var {
-                stack_location: m_stack_pointer,
-                line_number,
-            },
-        );
-    }
+// 32-bit view of a 64-bit register, X12 => W12
+pub fn w_reg(register: &str) -> String {
+    format!("W{}", &register[1..])
 }
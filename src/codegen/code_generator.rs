@@ -1,169 +1,263 @@
+use tracing::debug;
+
 use crate::{
-    lexical::Token,
-    parsing::{
-        NodeElse, NodeFunc, NodeRoot, NodeScope, NodeStmt,
-    },
+    ir::{BlockId, IrBlock, IrFunction, IrGlobal, IrModule, LocalId, Terminator, VReg},
+    report_pass, OptLevel, Options,
 };
 
+use code_gen_expr::{global_label, string_label};
+use code_gen_runtime::{PRINT_STRINGS, READ_STRINGS};
+use code_gen_instr::{imm, label, reg, Cond, Instr, Operand};
+use code_gen_regalloc::{Allocation, Location, Value};
+use code_gen_stack::{SCRATCH_DST, SCRATCH_LHS, SCRATCH_RHS};
 use code_gen_structs::Generator;
 
 
+// Function
+
 impl Generator {
-    fn create_label(&mut self) -> String {
-        let label = format!("label{}", self.label_index);
-        self.label_index += 1;
-        label
+    /// Read-only data with the length of each string literal followed by its
+    /// bytes, a str value is the address of the length.
+    fn gen_strings(&mut self, strings: &[String]) {
+        let mut records: Vec<(String, &str)> =
+            strings.iter().enumerate().map(|(index, string)| (string_label(index), string.as_str())).collect();
+        if self.uses_print {
+            records.extend(PRINT_STRINGS.iter().map(|(name, string)| (name.to_string(), *string)));
+        }
+        if self.uses_read {
+            records.extend(READ_STRINGS.iter().map(|(name, string)| (name.to_string(), *string)));
+        }
+        if records.is_empty() {
+            return;
+        }
+        self.directive(".const");
+        for (name, string) in records {
+            self.directive(".p2align 3");
+            self.label(&name);
+            self.directive(&format!(".quad {}", string.len()));
+            if !string.is_empty() {
+                self.directive(&format!(".ascii \"{}\"", escape_ascii(string)));
+            }
+        }
     }
-}
 
+    /// Writable data with the initial value of every static.
+    fn gen_globals(&mut self, globals: &[IrGlobal]) {
+        if globals.is_empty() {
+            return;
+        }
+        self.directive(".data");
+        for (index, global) in globals.iter().enumerate() {
+            let directive = match global.ty.bits() {
+                8 => ".byte",
+                32 => ".long",
+                _ => ".quad",
+            };
+            self.directive(&format!(".p2align {}", (global.ty.bits() / 8).trailing_zeros()));
+            self.label(&global_label(index));
+            self.directive(&format!("{} {}", directive, global.ty.value_of(global.value)));
+        }
+    }
 
-// Statement
-
-impl Generator {
-    fn gen_func(&mut self, func: &NodeFunc) {
-        let f_name = &func.f_name;
-        let stmts = &func.stmts;
-        self.begin_func(f_name.to_string());
+    fn gen_func(&mut self, func: &IrFunction, alloc: Allocation) {
+        let f_name = &func.name;
+        debug!("{} allocation {:?}", f_name, alloc);
+        self.begin_func(func, alloc);
         tracing::debug!("Generating func {}", f_name);
 
         if f_name == "main" {
-            self.buffer.push("_start:\n".to_owned());
-            for stmt in stmts {
-                debug!("main => {:?}", stmt);
-                self.generate_stmt(stmt);
-            }
-            self.end_func();
+            self.label("_start");
         } else {
-            self.buffer_push(&format!("_{}:", f_name));
-            for stmt in stmts {
-                self.generate_stmt(stmt);
+            self.label(&format!("_{}", f_name));
+        }
+
+        self.gen_prologue();
+        for index in 0..func.params {
+            // Unused parameters never got a home
+            let param = Value::Local(LocalId(index));
+            if self.m_alloc.locations.contains_key(&param) {
+                self.move_into(param, &format!("X{}", index));
             }
-            self.end_func();
-            self.buffer.push("RET\n".to_owned());
+        }
+
+        for block in func.blocks.iter() {
+            debug!("{} => {:?}", f_name, block);
+            self.gen_block(block);
         }
     }
 
-    fn generate_stmt(&mut self, ele: &NodeStmt) {
-        match ele {
-            crate::parsing::NodeStmt::Let { expr, ident } => {
-                let identifier = cast!(&ident.token, Token::Indent);
-                self.comment(&format!("Let stmt {}", identifier));
-                let variable = self.get_variable(&identifier);
-                if let Some(variable) = variable {
-                    debug!("Generator {:#?}", self);
-                    panic!(
-                        "{} already defined at line {}",
-                        identifier, variable.line_number
-                    );
-                }
+    fn gen_block(&mut self, block: &IrBlock) {
+        self.label(&self.block_label(block.id));
+        for inst in block.insts.iter() {
+            self.gen_inst(inst);
+        }
+        self.gen_terminator(&block.terminator);
+    }
 
-                self.parse_expr(expr);
-                self.insert_ident(&identifier, ident.line);
-            }
-            crate::parsing::NodeStmt::Return { expr } => {
+    fn gen_terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Ret(value) => {
                 self.comment("Return stmt");
-                self.parse_expr(expr);
-                self.pop("X0");
-                self.end_func();
-                self.buffer_push(&format!("mov X16, #1"));
-                self.buffer_push(&format!("svc #0x80"));
+                match value {
+                    Some(value) => {
+                        let src = self.use_reg(Value::VReg(*value), "X0");
+                        if src != "X0" {
+                            self.emit("MOV", vec![reg("X0"), reg(src)]);
+                        }
+                    }
+                    // A main without a return type exits with 0
+                    None if self.m_func_name == "main" => self.emit("MOV", vec![reg("X0"), imm(0)]),
+                    None => {}
+                }
+                if self.m_func_name == "main" {
+                    self.emit("MOV", vec![reg("X16"), imm(1)]);
+                    self.emit("SVC", vec![imm(0x80)]);
+                } else {
+                    self.gen_epilogue();
+                    self.emit("RET", vec![]);
+                }
             }
-            crate::parsing::NodeStmt::Scope { scope } => {
-                self.generate_scope(scope);
+            Terminator::Jump(target) => {
+                self.emit("B", vec![label(&self.block_label(*target))]);
             }
-            NodeStmt::If { expr, scope, chain } => {
-                self.comment("If condition");
-
-                // Normal is Rest of the code
-                // Next Label is Next condition like else if or else
-                let normal_label = self.create_label();
-                let next_label = self.create_label();
-
-                self.parse_expr(expr);
-                self.pop("X1");
-                self.buffer_push(&format!("cmp X1, 0"));
-                self.buffer_push(&format!("b.eq {}", next_label));
-                self.comment("If scope generation start");
-                self.generate_scope(scope);
-                self.buffer_push(&format!("MOV X2, 0"));
-                self.buffer_push(&format!("cmp X2, 0"));
-                self.buffer_push(&format!("b.eq {}", normal_label));
-                self.buffer_push(&format!("{}:", next_label));
-                self.generate_node_else(&chain, &normal_label);
-                self.comment("If condition finished");
-                self.buffer_push(&format!("{}:", normal_label));
+            Terminator::Branch { cond, then_block, else_block } => {
+                let cond = self.use_reg(Value::VReg(*cond), SCRATCH_LHS);
+                self.emit("CMP", vec![reg(cond), imm(0)]);
+                self.buffer.push(Instr::BCond(Cond::Eq, self.block_label(*else_block)));
+                self.emit("B", vec![label(&self.block_label(*then_block))]);
             }
-            NodeStmt::ReAssign { expr, ident } => {
-                let identifier = cast!(&ident.token, Token::Indent);
-                let variable = self.get_variable(&identifier).expect(&format!(
-                    "{} not declared but used in line {}",
-                    identifier, ident.line
-                ));
-                // Let's mStackPos=10 and varStackPos=5
-                // offset=5*16=80 we need to go upwards to access memory
-                // Meaning we have to use -80 rather +80
-                let offset = self.current_stack_pointer() - variable.stack_location;
-                let offset = offset * 16;
-                self.parse_expr(expr);
-                self.pop("X1");
-                self.buffer.push(format!("STP X1, X2, [SP, #{}]\n", offset));
+            Terminator::Switch { value, cases, default } => self.gen_switch(*value, cases, *default),
+            Terminator::TailCall { func, args } => {
+                self.comment("Tail call");
+                self.gen_args(args);
+                self.gen_epilogue();
+                self.emit("B", vec![label(&format!("_{}", func))]);
+            }
+            Terminator::Unreachable => {
+                self.emit("BRK", vec![imm(1)]);
             }
         }
     }
 
-    fn generate_node_else(&mut self, node_else: &Option<NodeElse>, normal_label: &str) {
-        if let Some(node_else) = node_else {
-            match node_else {
-                NodeElse::ElseIf { expr, scope, chain } => {
-                    let pointer = self.current_stack_pointer();
-                    self.comment(&format!("Else Compiler mStackPointer {}", pointer));
-                    self.parse_expr(expr);
-                    self.pop("X1");
-                    let label = self.create_label();
-                    self.buffer.push(format!("cmp X1, 0\n"));
-                    self.buffer.push(format!("b.eq {}\n", label));
-                    self.comment("Else If scope generation start");
-                    self.generate_scope(scope);
-                    self.buffer.push(format!("MOV X2, 0\n"));
-                    self.buffer.push(format!("cmp X2, 0\n"));
-                    self.buffer.push(format!("b.eq {}\n", normal_label));
-                    self.buffer.push(format!("{}:\n", label));
-                    self.generate_node_else(chain, normal_label);
-                }
-                NodeElse::Else(scope) => {
-                    self.comment("Else scope generation start");
-                    self.generate_scope(scope);
-                }
+    /// Dense cases index a table of offsets to their blocks, sparse ones are
+    /// compared one after the other.
+    fn gen_switch(&mut self, value: VReg, cases: &[(i64, BlockId)], default: BlockId) {
+        let value = self.use_reg(Value::VReg(value), SCRATCH_LHS);
+        let min = cases.iter().map(|(case, _)| *case).min().unwrap_or(0);
+        let max = cases.iter().map(|(case, _)| *case).max().unwrap_or(0);
+        let span = max as i128 - min as i128 + 1;
+        if cases.len() < 4 || span > 2 * cases.len() as i128 || span >= 4096 {
+            self.comment("Switch");
+            for (case, target) in cases {
+                self.gen_compare_imm(value, *case);
+                self.buffer.push(Instr::BCond(Cond::Eq, self.block_label(*target)));
+            }
+            self.emit("B", vec![label(&self.block_label(default))]);
+            return;
+        }
+
+        self.comment("Switch through a jump table");
+        let index = match min {
+            0 => value,
+            1..4096 => {
+                self.emit("SUB", vec![reg(SCRATCH_RHS), reg(value), imm(min)]);
+                SCRATCH_RHS
+            }
+            -4095..0 => {
+                self.emit("ADD", vec![reg(SCRATCH_RHS), reg(value), imm(-min)]);
+                SCRATCH_RHS
+            }
+            _ => {
+                self.load_bits(SCRATCH_RHS, min as u64);
+                self.emit("SUB", vec![reg(SCRATCH_RHS), reg(value), reg(SCRATCH_RHS)]);
+                SCRATCH_RHS
             }
+        };
+        // Below `min` wraps around to a large unsigned index
+        self.emit("CMP", vec![reg(index), imm(span as i64)]);
+        self.buffer.push(Instr::BCond(Cond::Hs, self.block_label(default)));
+        let table = self.local_label("table");
+        self.emit("ADR", vec![reg(SCRATCH_DST), label(&table)]);
+        self.emit("LDR", vec![reg(SCRATCH_RHS), Operand::Indexed(SCRATCH_DST.to_owned(), index.to_owned())]);
+        self.emit("ADD", vec![reg(SCRATCH_DST), reg(SCRATCH_DST), reg(SCRATCH_RHS)]);
+        self.emit("BR", vec![reg(SCRATCH_DST)]);
+
+        self.directive(".p2align 3");
+        self.label(&table);
+        for offset in 0..span as i64 {
+            let target = cases
+                .iter()
+                .find(|(case, _)| *case == min + offset)
+                .map_or(default, |(_, target)| *target);
+            self.directive(&format!(".quad {} - {}", self.block_label(target), table));
         }
     }
 
-    fn generate_scope(&mut self, scope: &NodeScope) {
-        let scope_stmts = &scope.0;
-        self.begin_scope();
-        let begin_stack_pointer = self.current_stack_pointer();
-        for scope_stmt in scope_stmts.into_iter() {
-            self.generate_stmt(scope_stmt);
-        }
-        let current_stack_pointer = self.current_stack_pointer();
-        let difference = current_stack_pointer - begin_stack_pointer;
-        self.clear_stack(difference);
-        self.end_scope();
+    // CMP against any 64-bit constant, through a scratch register past the 12-bit immediates
+    fn gen_compare_imm(&mut self, register: &str, value: i64) {
+        match value {
+            0..4096 => self.emit("CMP", vec![reg(register), imm(value)]),
+            -4095..0 => self.emit("CMN", vec![reg(register), imm(-value)]),
+            _ => {
+                self.load_bits(SCRATCH_RHS, value as u64);
+                self.emit("CMP", vec![reg(register), reg(SCRATCH_RHS)]);
+            }
+        }
     }
 }
 
-pub fn generate_code(node_root: NodeRoot) {
-    let funcs = node_root.funcs;
-    let mut generator = Generator::new();
+pub fn generate_code(module: &IrModule, options: &Options) -> String {
+    let mut generator = Generator::new(options.runtime_checks);
+    generator.structs = module.structs.clone();
 
-    generator.buffer_push(&format!(".global _start"));
-    generator.buffer_push(&format!(".align 2"));
+    generator.directive(".global _start");
+    generator.directive(".align 2");
 
-    for ele in funcs {
-        generator.gen_func(&ele);
+    let optimize = options.opt_level >= OptLevel::O1;
+    for func in module.funcs.iter() {
+        let alloc = if optimize {
+            code_gen_regalloc::allocate(func, &module.structs)
+        } else {
+            code_gen_regalloc::allocate_on_stack(func, &module.structs)
+        };
+        if options.print_passes {
+            let in_registers =
+                alloc.locations.values().filter(|location| matches!(location, Location::Reg(_))).count();
+            report_pass(
+                &format!("register-allocation {}", func.name),
+                &format!("{} in registers, {} on the stack", in_registers, alloc.slot_count),
+            );
+        }
+        generator.gen_func(func, alloc);
     }
 
-    generator.finalize();
+    generator.gen_runtime();
+    generator.gen_traps();
+    generator.gen_strings(&module.strings);
+    generator.gen_globals(&module.globals);
+    generator.gen_runtime_data();
+
+    if optimize {
+        let before = generator.buffer.iter().filter(|instr| instr.is_code()).count();
+        generator.buffer = code_gen_peephole::optimize(generator.buffer);
+        if options.print_passes {
+            let after = generator.buffer.iter().filter(|instr| instr.is_code()).count();
+            report_pass("peephole", &format!("instructions {} -> {}", before, after));
+        }
+    }
+    generator.finalize()
+}
+
+// Printable ASCII stays readable, everything else becomes an octal escape
+fn escape_ascii(string: &str) -> String {
+    string
+        .bytes()
+        .map(|byte| match byte {
+            b'"' | b'\\' => format!("\\{}", byte as char),
+            b' '..=b'~' => (byte as char).to_string(),
+            _ => format!("\\{:03o}", byte),
+        })
+        .collect()
 }
 
 #[macro_export]
@@ -178,7 +272,5 @@ macro_rules! cast {
     }};
 }
 
-pub(crate) use cast;
-use tracing::debug;
 
-use super::code_gen_structs;
+use super::{code_gen_expr, code_gen_instr, code_gen_peephole, code_gen_runtime, code_gen_regalloc, code_gen_stack, code_gen_structs};
@@ -0,0 +1,33 @@
+
+mod ir_structs;
+mod ir_lower;
+mod ir_layout;
+mod ir_printer;
+mod ir_dce;
+mod ir_inline;
+mod ir_tail_call;
+mod ir_const_fold;
+
+pub use ir_structs::*;
+
+use crate::parsing::NodeRoot;
+
+pub fn lower(node_root: &NodeRoot) -> IrModule {
+    ir_lower::lower_module(node_root)
+}
+
+pub fn eliminate_dead_code(module: &mut IrModule, keep_traps: bool) {
+    ir_dce::eliminate_dead_code(module, keep_traps)
+}
+
+pub fn inline_calls(module: &mut IrModule) {
+    ir_inline::inline_calls(module)
+}
+
+pub fn mark_tail_calls(module: &mut IrModule) {
+    ir_tail_call::mark_tail_calls(module)
+}
+
+pub fn fold_constants(module: &mut IrModule) {
+    ir_const_fold::fold_constants(module)
+}
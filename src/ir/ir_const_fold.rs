@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use super::ir_structs::{BinaryOp, IrFunction, IrInst, IrModule, IrType, Terminator, VReg};
+
+/// Computes arithmetic and casts on constants at compile time and turns branches and
+/// switches on a constant into jumps. Anything that would overflow its type or
+/// divide by zero is left to the runtime, checked or not.
+pub fn fold_constants(module: &mut IrModule) {
+    for func in module.funcs.iter_mut() {
+        let constants = fold_insts(func);
+        for block in func.blocks.iter_mut() {
+            let target = match &block.terminator {
+                Terminator::Branch { cond, then_block, else_block } => {
+                    let Some(&value) = constants.get(cond) else { continue };
+                    if value != 0 { *then_block } else { *else_block }
+                }
+                Terminator::Switch { value, cases, default } => {
+                    let Some(&value) = constants.get(value) else { continue };
+                    cases.iter().find(|(case, _)| *case == value).map_or(*default, |(_, target)| *target)
+                }
+                _ => continue,
+            };
+            block.terminator = Terminator::Jump(target);
+        }
+    }
+}
+
+// Registers are assigned once, so a constant holds wherever it is read.
+// Returns the value of every register known at compile time.
+fn fold_insts(func: &mut IrFunction) -> HashMap<VReg, i64> {
+    let mut constants = HashMap::new();
+    // An operand may be defined in a block further down, go until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for inst in func.blocks.iter_mut().flat_map(|block| block.insts.iter_mut()) {
+            match *inst {
+                IrInst::Const { dst, value } => {
+                    changed |= constants.insert(dst, value).is_none();
+                }
+                IrInst::Binary { dst, op, lhs, rhs } => {
+                    let ty = func.vregs[lhs.0];
+                    let (Some(&lhs), Some(&rhs)) = (constants.get(&lhs), constants.get(&rhs)) else { continue };
+                    if let Some(value) = evaluate(op, ty, lhs, rhs) {
+                        *inst = IrInst::Const { dst, value };
+                        constants.insert(dst, value);
+                        changed = true;
+                    }
+                }
+                IrInst::Cast { dst, src } => {
+                    let Some(&value) = constants.get(&src) else { continue };
+                    let value = convert(func.vregs[src.0], func.vregs[dst.0], value);
+                    *inst = IrInst::Const { dst, value };
+                    constants.insert(dst, value);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+    }
+    constants
+}
+
+// `ty` is the type of the operands
+fn evaluate(op: BinaryOp, ty: IrType, lhs: i64, rhs: i64) -> Option<i64> {
+    if ty == IrType::F64 {
+        return Some(evaluate_float(op, f64::from_bits(lhs as u64), f64::from_bits(rhs as u64)));
+    }
+    let (lhs, rhs) = (ty.value_of(lhs), ty.value_of(rhs));
+    let value = match op {
+        BinaryOp::Add => lhs.checked_add(rhs)?,
+        BinaryOp::Sub => lhs.checked_sub(rhs)?,
+        BinaryOp::Mul => lhs.checked_mul(rhs)?,
+        BinaryOp::Div => lhs.checked_div(rhs)?,
+        BinaryOp::Eq => return Some((lhs == rhs) as i64),
+        BinaryOp::Lt => return Some((lhs < rhs) as i64),
+        BinaryOp::Le => return Some((lhs <= rhs) as i64),
+        BinaryOp::Gt => return Some((lhs > rhs) as i64),
+        BinaryOp::Ge => return Some((lhs >= rhs) as i64),
+        BinaryOp::Or => return Some((lhs != 0 || rhs != 0) as i64),
+        BinaryOp::And => return Some((lhs != 0 && rhs != 0) as i64),
+    };
+    let (min, max) = ty.range();
+    (min..=max).contains(&value).then_some(value as i64)
+}
+
+// Float arithmetic never traps, the result is what the hardware computes
+fn evaluate_float(op: BinaryOp, lhs: f64, rhs: f64) -> i64 {
+    let value = match op {
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div => lhs / rhs,
+        BinaryOp::Eq => return (lhs == rhs) as i64,
+        BinaryOp::Lt => return (lhs < rhs) as i64,
+        BinaryOp::Le => return (lhs <= rhs) as i64,
+        BinaryOp::Gt => return (lhs > rhs) as i64,
+        BinaryOp::Ge => return (lhs >= rhs) as i64,
+        BinaryOp::Or | BinaryOp::And => unreachable!("Logical operators are not defined for f64"),
+    };
+    value.to_bits() as i64
+}
+
+// Bits of `value` cast from `from` to `to`. Floats saturate when they become
+// integers, NaN becomes 0.
+fn convert(from: IrType, to: IrType, value: i64) -> i64 {
+    match (from, to) {
+        (IrType::F64, IrType::F64) => value,
+        (IrType::F64, to) => {
+            let (min, max) = to.range();
+            (f64::from_bits(value as u64) as i128).clamp(min, max) as i64
+        }
+        (from, IrType::F64) => (from.value_of(value) as f64).to_bits() as i64,
+        (from, to) => to.wrap(from.value_of(value)),
+    }
+}
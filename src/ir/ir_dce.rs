@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ir_structs::{BinaryOp, BlockId, IrFunction, IrInst, IrModule, LocalId, Terminator, VReg};
+
+/// Removes functions `main` never calls, blocks control never reaches, and
+/// variables that are written but never read. With `keep_traps` arithmetic
+/// that could hit a runtime check is kept even when its result is unused.
+pub fn eliminate_dead_code(module: &mut IrModule, keep_traps: bool) {
+    strip_unreachable_funcs(module);
+    for func in module.funcs.iter_mut() {
+        strip_unreachable_blocks(func);
+        strip_dead_locals(func, keep_traps);
+    }
+}
+
+fn strip_unreachable_funcs(module: &mut IrModule) {
+    let by_name: HashMap<&str, usize> =
+        module.funcs.iter().enumerate().map(|(index, func)| (func.name.as_str(), index)).collect();
+
+    let mut reachable = HashSet::from(["main"]);
+    let mut pending = vec!["main"];
+    while let Some(name) = pending.pop() {
+        let func = &module.funcs[by_name[name]];
+        for block in func.blocks.iter() {
+            let calls = block.insts.iter().filter_map(|inst| match inst {
+                IrInst::Call { func, .. } => Some(func),
+                _ => None,
+            });
+            let tail_call = match &block.terminator {
+                Terminator::TailCall { func, .. } => Some(func),
+                _ => None,
+            };
+            for callee in calls.chain(tail_call) {
+                if reachable.insert(callee.as_str()) {
+                    pending.push(callee.as_str());
+                }
+            }
+        }
+    }
+
+    let reachable: HashSet<String> = reachable.into_iter().map(str::to_owned).collect();
+    module.funcs.retain(|func| reachable.contains(&func.name));
+}
+
+// Also drops code after a `return`, which lowering leaves in blocks without predecessors
+fn strip_unreachable_blocks(func: &mut IrFunction) {
+    let mut reachable = vec![false; func.blocks.len()];
+    let mut pending = vec![BlockId(0)];
+    while let Some(id) = pending.pop() {
+        if std::mem::replace(&mut reachable[id.0], true) {
+            continue;
+        }
+        pending.extend(func.blocks[id.0].terminator.successors());
+    }
+
+    func.blocks.retain(|block| reachable[block.id.0]);
+    func.renumber_blocks();
+}
+
+fn strip_dead_locals(func: &mut IrFunction, keep_traps: bool) {
+    let insts = || func.blocks.iter().flat_map(|block| block.insts.iter());
+    // Parameters keep their place even when unused, arguments are matched by position.
+    // A write to an array element can trap on its index, like arithmetic.
+    // A struct whose address is taken counts as read, it is reached through that.
+    let read: HashSet<LocalId> = insts()
+        .filter_map(|inst| match inst {
+            IrInst::Load { local, .. } | IrInst::LoadIndex { local, .. } | IrInst::Addr { local, .. } => Some(*local),
+            IrInst::StoreIndex { local, .. } if keep_traps => Some(*local),
+            _ => None,
+        })
+        .chain((0..func.params).map(LocalId))
+        .collect();
+    for block in func.blocks.iter_mut() {
+        block.insts.retain(|inst| match inst {
+            IrInst::Store { local, .. } | IrInst::StoreIndex { local, .. } | IrInst::Fill { local, .. } => {
+                read.contains(local)
+            }
+            _ => true,
+        });
+    }
+
+    // The stored values may now be unused, and so may their operands
+    loop {
+        let used: HashSet<VReg> = func
+            .blocks
+            .iter()
+            .flat_map(|block| block.insts.iter().flat_map(|inst| inst.operands()).chain(block.terminator.operands()))
+            .collect();
+        let mut changed = false;
+        for block in func.blocks.iter_mut() {
+            let before = block.insts.len();
+            block.insts.retain(|inst| {
+                let unused = inst.dst().is_some_and(|dst| !used.contains(&dst));
+                !(unused && is_pure(inst, keep_traps))
+            });
+            changed |= block.insts.len() != before;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Renumber the locals that are left
+    let mut remap = HashMap::new();
+    let mut index = 0;
+    func.locals.retain(|_| {
+        let local = LocalId(index);
+        index += 1;
+        if read.contains(&local) {
+            remap.insert(local, LocalId(remap.len()));
+        }
+        read.contains(&local)
+    });
+    for inst in func.blocks.iter_mut().flat_map(|block| block.insts.iter_mut()) {
+        if let Some(local) = inst.local_mut() {
+            *local = remap[local];
+        }
+    }
+}
+
+fn is_pure(inst: &IrInst, keep_traps: bool) -> bool {
+    match inst {
+        IrInst::Const { .. }
+        | IrInst::Load { .. }
+        | IrInst::LoadGlobal { .. }
+        | IrInst::Cast { .. }
+        | IrInst::Str { .. }
+        | IrInst::Addr { .. }
+        | IrInst::FieldAddr { .. }
+        | IrInst::LoadField { .. } => true,
+        IrInst::Binary { op, .. } => {
+            let can_trap = matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div);
+            !(keep_traps && can_trap)
+        }
+        IrInst::LoadIndex { .. } => !keep_traps,
+        IrInst::Store { .. }
+        | IrInst::StoreGlobal { .. }
+        | IrInst::StoreIndex { .. }
+        | IrInst::Fill { .. }
+        | IrInst::StoreField { .. }
+        | IrInst::Copy { .. }
+        | IrInst::Call { .. }
+        | IrInst::Print { .. }
+        | IrInst::Read { .. } => false,
+    }
+}
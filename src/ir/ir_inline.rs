@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parsing::InlineHint;
+
+use super::ir_structs::{BlockId, IrBlock, IrFunction, IrInst, IrLocal, IrModule, IrType, LocalId, Terminator, VReg};
+
+/// Functions up to this many instructions, terminators included, are inlined
+/// without `#[inline]`.
+const INLINE_SIZE_LIMIT: usize = 8;
+
+/// Replaces calls to small or `#[inline]` functions with a copy of their body.
+/// Recursive functions and `#[inline(never)]` are always called.
+pub fn inline_calls(module: &mut IrModule) {
+    let recursive = recursive_funcs(module);
+    let inlinable: HashMap<String, IrFunction> = module
+        .funcs
+        .iter()
+        .filter(|func| func.name != "main" && !recursive.contains(&func.name) && wants_inline(func))
+        .map(|func| (func.name.clone(), func.clone()))
+        .collect();
+
+    for func in module.funcs.iter_mut() {
+        // Calls inside an inlined body are picked up on the next round
+        while let Some((block, index, callee)) = find_call(func, &inlinable) {
+            tracing::debug!("Inlining {} into {}", callee.name, func.name);
+            inline_call(func, block, index, callee);
+        }
+        merge_blocks(func);
+    }
+}
+
+fn wants_inline(func: &IrFunction) -> bool {
+    match func.inline {
+        Some(InlineHint::Always) => true,
+        Some(InlineHint::Never) => false,
+        None => {
+            let size: usize = func.blocks.iter().map(|block| block.insts.len() + 1).sum();
+            size <= INLINE_SIZE_LIMIT
+        }
+    }
+}
+
+fn callees(func: &IrFunction) -> impl Iterator<Item = &str> {
+    func.blocks.iter().flat_map(|block| {
+        let calls = block.insts.iter().filter_map(|inst| match inst {
+            IrInst::Call { func, .. } => Some(func.as_str()),
+            _ => None,
+        });
+        let tail_call = match &block.terminator {
+            Terminator::TailCall { func, .. } => Some(func.as_str()),
+            _ => None,
+        };
+        calls.chain(tail_call)
+    })
+}
+
+// Functions that can reach themselves through the call graph
+fn recursive_funcs(module: &IrModule) -> HashSet<String> {
+    let graph: HashMap<&str, Vec<&str>> =
+        module.funcs.iter().map(|func| (func.name.as_str(), callees(func).collect())).collect();
+
+    let mut recursive = HashSet::new();
+    for func in module.funcs.iter() {
+        let mut seen = HashSet::new();
+        let mut pending = graph[func.name.as_str()].clone();
+        while let Some(name) = pending.pop() {
+            if name == func.name {
+                recursive.insert(func.name.clone());
+                break;
+            }
+            if seen.insert(name) {
+                pending.extend(graph[name].iter());
+            }
+        }
+    }
+    recursive
+}
+
+fn find_call<'a>(
+    func: &IrFunction,
+    inlinable: &'a HashMap<String, IrFunction>,
+) -> Option<(usize, usize, &'a IrFunction)> {
+    for (block_index, block) in func.blocks.iter().enumerate() {
+        for (inst_index, inst) in block.insts.iter().enumerate() {
+            if let IrInst::Call { func: name, .. } = inst {
+                if let Some(callee) = inlinable.get(name) {
+                    return Some((block_index, inst_index, callee));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Splits the block at the call, the callee's blocks go in between:
+//
+// bb1: insts before the call; jmp to the callee's entry
+// callee blocks, each `ret v` becomes a jump to the continuation
+// continuation: insts after the call; the original terminator
+fn inline_call(func: &mut IrFunction, block_index: usize, inst_index: usize, callee: &IrFunction) {
+    let vreg_base = func.vregs.len();
+    func.vregs.extend(callee.vregs.iter().copied());
+    let local_base = func.locals.len();
+    for local in callee.locals.iter() {
+        let name = unique_local_name(func, &format!("{}.{}", callee.name, local.name));
+        func.locals.push(IrLocal { name, ty: local.ty, len: local.len });
+    }
+
+    // Ids past every existing block, sorted out by the renumbering below
+    let block_base = func.blocks.len();
+    let continuation_id = BlockId(block_base + callee.blocks.len());
+
+    let block = &mut func.blocks[block_index];
+    let after_call = block.insts.split_off(inst_index + 1);
+    let Some(IrInst::Call { dst, args, .. }) = block.insts.pop() else {
+        unreachable!("find_call points at a call")
+    };
+    for (index, src) in args.into_iter().enumerate() {
+        block.insts.push(IrInst::Store { local: LocalId(local_base + index), src });
+    }
+    let terminator = std::mem::replace(&mut block.terminator, Terminator::Jump(BlockId(block_base)));
+    let mut continuation = IrBlock { id: continuation_id, insts: after_call, terminator };
+
+    let mut body: Vec<IrBlock> = callee.blocks.clone();
+    // (index in body, returned value)
+    let mut returned = vec![];
+    for (index, block) in body.iter_mut().enumerate() {
+        block.id = BlockId(block_base + block.id.0);
+        for inst in block.insts.iter_mut() {
+            inst.map_vregs(|vreg| VReg(vreg_base + vreg.0));
+            if let Some(local) = inst.local_mut() {
+                *local = LocalId(local_base + local.0);
+            }
+        }
+        block.terminator.map_vregs(|vreg| VReg(vreg_base + vreg.0));
+        block.terminator.map_targets(|target| BlockId(block_base + target.0));
+        match std::mem::replace(&mut block.terminator, Terminator::Jump(continuation_id)) {
+            Terminator::Ret(value) => returned.push((index, value)),
+            // Only the caller's frame is left to reuse, so it becomes a plain call again
+            Terminator::TailCall { func: name, args } => {
+                func.vregs.push(callee.ret_type);
+                let value = VReg(func.vregs.len() - 1);
+                block.insts.push(IrInst::Call { dst: value, func: name, args });
+                returned.push((index, Some(value)));
+            }
+            terminator => block.terminator = terminator,
+        }
+    }
+
+    if callee.ret_type == IrType::Unit {
+        // Nothing reads the result of the call
+    } else if let [(_, Some(value))] = returned[..] {
+        // The only return dominates the continuation, so its register can stand in for the call
+        for block in func.blocks.iter_mut().chain(std::iter::once(&mut continuation)) {
+            for inst in block.insts.iter_mut() {
+                inst.map_vregs(|vreg| if vreg == dst { value } else { vreg });
+            }
+            block.terminator.map_vregs(|vreg| if vreg == dst { value } else { vreg });
+        }
+    } else {
+        // Several returns meet in the continuation through a local
+        let name = unique_local_name(func, &format!("{}.ret", callee.name));
+        func.locals.push(IrLocal { name, ty: callee.ret_type, len: None });
+        let local = LocalId(func.locals.len() - 1);
+        for (index, value) in returned {
+            let value = value.expect("Functions with a return type return a value");
+            body[index].insts.push(IrInst::Store { local, src: value });
+        }
+        continuation.insts.insert(0, IrInst::Load { dst, local });
+    }
+
+    body.push(continuation);
+    func.blocks.splice(block_index + 1..block_index + 1, body);
+    func.renumber_blocks();
+}
+
+// Folds a block into its only predecessor when that one just jumps to it,
+// which undoes the split around every inlined call.
+fn merge_blocks(func: &mut IrFunction) {
+    loop {
+        let mut predecessors: HashMap<BlockId, usize> = HashMap::new();
+        for block in func.blocks.iter() {
+            for target in block.terminator.successors() {
+                *predecessors.entry(target).or_default() += 1;
+            }
+        }
+        let merge = func.blocks.iter().position(|block| match block.terminator {
+            Terminator::Jump(target) => target != block.id && target.0 != 0 && predecessors[&target] == 1,
+            _ => false,
+        });
+        let Some(index) = merge else { return };
+
+        let Terminator::Jump(target) = func.blocks[index].terminator else { unreachable!() };
+        let target_index = func.blocks.iter().position(|block| block.id == target).unwrap();
+        let merged = func.blocks.remove(target_index);
+        let index = if target_index < index { index - 1 } else { index };
+        func.blocks[index].insts.extend(merged.insts);
+        func.blocks[index].terminator = merged.terminator;
+        func.renumber_blocks();
+    }
+}
+
+fn unique_local_name(func: &IrFunction, base: &str) -> String {
+    let taken = |name: &str| func.locals.iter().any(|local| local.name == name);
+    let mut name = base.to_owned();
+    let mut count = 0;
+    while taken(&name) {
+        count += 1;
+        name = format!("{}.{}", base, count);
+    }
+    name
+}
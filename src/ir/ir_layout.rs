@@ -0,0 +1,134 @@
+use crate::{
+    cast,
+    lexical::{LitKind, Token},
+    parsing::NodeRoot,
+};
+
+use super::ir_structs::{IrField, IrStruct, IrType};
+
+/// How the declared types are held. Structs and enums whose variants carry
+/// values live in memory and get a layout in `structs`, the structs first.
+/// Enums without values are only their tag.
+pub struct Layouts {
+    pub structs: Vec<IrStruct>,
+    enums: Vec<IrType>,
+    // Variant names of each enum, a variant's index is its tag
+    variants: Vec<Vec<String>>,
+}
+
+impl Layouts {
+    /// Fields go in declaration order, each at the next offset that is a
+    /// multiple of its alignment, and the size is rounded up to the largest
+    /// alignment so a struct can follow another one. An enum is a u32 tag,
+    /// the index of its variant, followed by the values of the variant.
+    pub fn new(root: &NodeRoot) -> Layouts {
+        let mut data_enums = 0;
+        let enums = root
+            .enums
+            .iter()
+            .map(|node_enum| match node_enum.is_c_like() {
+                true => IrType::U32,
+                false => {
+                    data_enums += 1;
+                    IrType::Struct(root.structs.len() + data_enums - 1)
+                }
+            })
+            .collect();
+        let variants = root
+            .enums
+            .iter()
+            .map(|node_enum| node_enum.variants.iter().map(|variant| variant.name.to_owned()).collect())
+            .collect();
+        let mut layouts = Layouts { structs: vec![], enums, variants };
+        let mut pending: Vec<Option<IrStruct>> = vec![None; root.structs.len() + data_enums];
+        for id in 0..pending.len() {
+            layouts.layout(root, id, &mut pending);
+        }
+        layouts.structs = pending.into_iter().map(|layout| layout.unwrap()).collect();
+        layouts
+    }
+
+    pub fn ir_type(&self, kind: &LitKind) -> IrType {
+        match kind {
+            LitKind::Struct(id) => IrType::Struct(*id),
+            LitKind::Enum(id) => self.enums[*id],
+            kind => IrType::from(kind),
+        }
+    }
+
+    pub fn tag(&self, id: usize, variant: &str) -> i64 {
+        self.variants[id]
+            .iter()
+            .position(|name| name == variant)
+            .expect("Variant is validated before lowering") as i64
+    }
+
+    // Fields may name types declared later, those are laid out first
+    fn layout(&self, root: &NodeRoot, id: usize, pending: &mut Vec<Option<IrStruct>>) {
+        if pending[id].is_some() {
+            return;
+        }
+        let mut layout = match root.structs.get(id) {
+            Some(node_struct) => {
+                let fields = node_struct
+                    .fields
+                    .iter()
+                    .map(|field| (cast!(&field.ident.token, Token::Indent).to_owned(), field.kind));
+                let mut layout = IrStruct { name: node_struct.name.to_owned(), fields: vec![], size: 0, align: 1 };
+                self.append(root, &mut layout, fields, pending);
+                layout
+            }
+            None => {
+                let node_enum = root
+                    .enums
+                    .iter()
+                    .zip(self.enums.iter())
+                    .find_map(|(node_enum, ty)| (*ty == IrType::Struct(id)).then_some(node_enum))
+                    .expect("Layouts past the structs belong to enums");
+                let tag = IrField { name: "tag".to_owned(), ty: IrType::U32, offset: 0 };
+                let mut layout = IrStruct { name: node_enum.name.to_owned(), fields: vec![tag], size: 4, align: 4 };
+                // Every variant starts right after the tag, they overlap
+                for variant in node_enum.variants.iter() {
+                    let mut payload = IrStruct { name: String::new(), fields: vec![], size: 4, align: 4 };
+                    let fields = variant
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(index, kind)| (format!("{}.{}", variant.name, index), *kind));
+                    self.append(root, &mut payload, fields, pending);
+                    layout.fields.extend(payload.fields);
+                    layout.size = layout.size.max(payload.size);
+                    layout.align = layout.align.max(payload.align);
+                }
+                layout
+            }
+        };
+        layout.size = layout.size.next_multiple_of(layout.align);
+        pending[id] = Some(layout);
+    }
+
+    // Places `fields` after the ones already in `layout`
+    fn append(
+        &self,
+        root: &NodeRoot,
+        layout: &mut IrStruct,
+        fields: impl Iterator<Item = (String, LitKind)>,
+        pending: &mut Vec<Option<IrStruct>>,
+    ) {
+        for (name, kind) in fields {
+            let ty = self.ir_type(&kind);
+            let (size, align) = match ty {
+                IrType::Struct(inner) => {
+                    self.layout(root, inner, pending);
+                    let inner = pending[inner].as_ref().unwrap();
+                    (inner.size, inner.align)
+                }
+                ty => (ty.bits() as usize / 8, ty.bits() as usize / 8),
+            };
+            let offset = layout.size.next_multiple_of(align);
+            layout.fields.push(IrField { name, ty, offset });
+            layout.size = offset + size;
+            layout.align = layout.align.max(align);
+        }
+    }
+}
@@ -0,0 +1,879 @@
+use std::collections::HashMap;
+
+use crate::{
+    cast,
+    lexical::{LitKind, Token, TokenData},
+    parsing::{
+        NodeArray, NodeBiOp, NodeBlock, NodeElse, NodeExpr, NodeFunc, NodeMatch, NodePattern, NodeRoot, NodeScope,
+        NodeStmt, NodeTermExpr, Storage,
+    },
+};
+
+use super::{
+    ir_layout::Layouts,
+    ir_structs::{
+        BinaryOp, BlockId, IrBlock, IrFunction, IrGlobal, IrInst, IrLocal, IrModule, IrType, LocalId, Terminator,
+        VReg,
+    },
+};
+
+/// Arguments are passed in X0..X7.
+const MAX_PARAMS: usize = 8;
+
+pub fn lower_module(node_root: &NodeRoot) -> IrModule {
+    let types = Layouts::new(node_root);
+    let signatures: HashMap<&str, Signature> = node_root
+        .funcs
+        .iter()
+        .map(|func| (func.f_name.as_str(), Signature::new(func, &types)))
+        .collect();
+
+    // Constants become their value, statics are numbered in the order they are declared
+    let mut globals = HashMap::new();
+    let mut statics = vec![];
+    for node in node_root.globals.iter() {
+        let name = cast!(&node.ident.token, Token::Indent);
+        let ty = types.ir_type(&node.kind);
+        // u64 values past i64::MAX keep their bits
+        let value = node.value.expect("Globals are evaluated before lowering") as i64;
+        let global = match node.storage {
+            Storage::Const => Global::Const(ty, value),
+            Storage::Static { .. } => {
+                statics.push(IrGlobal { name: name.to_owned(), ty, value });
+                Global::Static(statics.len() - 1, ty)
+            }
+        };
+        globals.insert(name.as_str(), global);
+    }
+
+    let mut strings = vec![];
+    let funcs = node_root
+        .funcs
+        .iter()
+        .map(|func| FuncLowering::new(func, &signatures, &globals, &mut strings, &types).lower(func))
+        .collect();
+
+    IrModule { funcs, strings, structs: types.structs, globals: statics }
+}
+
+#[derive(Clone, Copy)]
+enum Global {
+    /// Type and bits of the value
+    Const(IrType, i64),
+    /// Index into the globals of the module
+    Static(usize, IrType),
+}
+
+struct Signature {
+    params: Vec<IrType>,
+    /// A returned struct is written through a hidden first parameter
+    ret_type: IrType,
+}
+
+impl Signature {
+    fn new(func: &NodeFunc, types: &Layouts) -> Signature {
+        if func.params.len() + returns_struct(func, types) as usize > MAX_PARAMS {
+            panic!("Function {} takes more than {} parameters", func.f_name, MAX_PARAMS);
+        }
+        let params = func.params.iter().map(|param| value_type(&param.kind, types)).collect();
+        Signature { params, ret_type: ret_type(func, types) }
+    }
+}
+
+fn ret_type(func: &NodeFunc, types: &Layouts) -> IrType {
+    func.return_type.as_ref().map_or(IrType::Unit, |kind| value_type(kind, types))
+}
+
+fn returns_struct(func: &NodeFunc, types: &Layouts) -> bool {
+    matches!(ret_type(func, types), IrType::Ptr(_))
+}
+
+// Type of a value in a register, structs and enums holding values are passed around by address
+fn value_type(kind: &LitKind, types: &Layouts) -> IrType {
+    match types.ir_type(kind) {
+        IrType::Struct(id) => IrType::Ptr(id),
+        ty => ty,
+    }
+}
+
+struct FuncLowering<'a> {
+    func: IrFunction,
+    signatures: &'a HashMap<&'a str, Signature>,
+    // Constants and statics, found when no local has the name
+    globals: &'a HashMap<&'a str, Global>,
+    // String literals of the whole module
+    strings: &'a mut Vec<String>,
+    types: &'a Layouts,
+    // Hidden parameter holding where a returned struct goes
+    ret_ptr: Option<LocalId>,
+    // Instructions of the block being filled, `None` once it got its terminator
+    current: Option<(BlockId, Vec<IrInst>)>,
+    // Blocks in the order they were filled, which is the order they are laid out in
+    layout: Vec<BlockId>,
+    // Lexical scopes, innermost last. Each maps a name to its local and the line it was declared on.
+    scopes: Vec<HashMap<String, (LocalId, i32)>>,
+    // How many locals were declared with a name, to keep local names unique in the dump
+    name_counts: HashMap<String, usize>,
+}
+
+impl<'a> FuncLowering<'a> {
+    fn new(
+        func: &NodeFunc,
+        signatures: &'a HashMap<&'a str, Signature>,
+        globals: &'a HashMap<&'a str, Global>,
+        strings: &'a mut Vec<String>,
+        types: &'a Layouts,
+    ) -> FuncLowering<'a> {
+        let func = IrFunction {
+            name: func.f_name.to_owned(),
+            ret_type: ret_type(func, types),
+            inline: func.inline,
+            params: func.params.len() + returns_struct(func, types) as usize,
+            locals: vec![],
+            vregs: vec![],
+            blocks: vec![],
+        };
+        FuncLowering {
+            func,
+            signatures,
+            globals,
+            strings,
+            types,
+            ret_ptr: None,
+            current: None,
+            layout: vec![],
+            scopes: vec![],
+            name_counts: HashMap::new(),
+        }
+    }
+
+    fn lower(mut self, func: &NodeFunc) -> IrFunction {
+        let entry = self.new_block();
+        self.switch_to(entry);
+        // Parameters are the outermost scope and the first locals
+        self.scopes.push(HashMap::new());
+        if let IrType::Ptr(id) = self.func.ret_type {
+            self.ret_ptr = Some(self.add_local("ret", IrType::Ptr(id), None));
+        }
+        for param in func.params.iter() {
+            let identifier = cast!(&param.ident.token, Token::Indent);
+            if let Some((_, line)) = self.lookup(identifier) {
+                panic!("{} already defined at line {}", identifier, line);
+            }
+            self.declare_local(identifier, param.ident.line, value_type(&param.kind, self.types), None);
+        }
+        self.lower_stmts(&func.stmts);
+        // Falling off the end returns from a function without a return type
+        if self.func.ret_type == IrType::Unit {
+            self.terminate(Terminator::Ret(None));
+        } else {
+            self.terminate(Terminator::Unreachable);
+        }
+
+        // Renumber blocks in layout order, so an `if` body follows its condition
+        let mut order = vec![0; self.func.blocks.len()];
+        for (index, id) in self.layout.iter().enumerate() {
+            order[id.0] = index;
+        }
+        let remap = |id: BlockId| BlockId(order[id.0]);
+        for block in self.func.blocks.iter_mut() {
+            block.id = remap(block.id);
+            block.terminator.map_targets(remap);
+        }
+        self.func.blocks.sort_by_key(|block| block.id.0);
+        self.func
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        let id = BlockId(self.func.blocks.len());
+        // Placeholder until `terminate` fills it in
+        self.func.blocks.push(IrBlock { id, insts: vec![], terminator: Terminator::Unreachable });
+        id
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.layout.push(block);
+        self.current = Some((block, vec![]));
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some((id, insts)) = self.current.take() {
+            let block = &mut self.func.blocks[id.0];
+            block.insts = insts;
+            block.terminator = terminator;
+        }
+    }
+
+    fn emit(&mut self, inst: IrInst) {
+        if self.current.is_none() {
+            // Code after a `return` still gets lowered, into a block nothing jumps to
+            let block = self.new_block();
+            self.switch_to(block);
+        }
+        self.current.as_mut().unwrap().1.push(inst);
+    }
+
+    fn new_vreg(&mut self, ty: IrType) -> VReg {
+        self.func.vregs.push(ty);
+        VReg(self.func.vregs.len() - 1)
+    }
+
+    fn lookup(&self, identifier: &str) -> Option<(LocalId, i32)> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(identifier).copied())
+    }
+
+    // Adds a local to the innermost scope, `len` is the number of elements of an array
+    fn declare_local(&mut self, identifier: &str, line: i32, ty: IrType, len: Option<usize>) -> LocalId {
+        let local = self.add_local(identifier, ty, len);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(identifier.to_owned(), (local, line));
+        local
+    }
+
+    // Adds a local no name resolves to, like the temporaries holding structs
+    fn add_local(&mut self, identifier: &str, ty: IrType, len: Option<usize>) -> LocalId {
+        let local = LocalId(self.func.locals.len());
+        let count = self.name_counts.entry(identifier.to_owned()).or_insert(0);
+        let name = match count {
+            0 => identifier.to_owned(),
+            _ => format!("{}.{}", identifier, count),
+        };
+        *count += 1;
+        self.func.locals.push(IrLocal { name, ty, len });
+        local
+    }
+}
+
+// Statement
+
+impl FuncLowering<'_> {
+    fn lower_stmts(&mut self, stmts: &[NodeStmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.lower_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn lower_scope(&mut self, scope: &NodeScope) {
+        self.lower_stmts(&scope.0);
+    }
+
+    fn lower_stmt(&mut self, stmt: &NodeStmt) {
+        match stmt {
+            NodeStmt::Let { ident, kind, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                if let Some((_, line)) = self.lookup(identifier) {
+                    panic!("{} already defined at line {}", identifier, line);
+                }
+
+                if let Some(IrType::Struct(id)) = kind.as_ref().map(|kind| self.types.ir_type(kind)) {
+                    // Built in place, the name only comes into scope afterwards
+                    let local = self.add_local(identifier, IrType::Struct(id), None);
+                    let dst = self.address(local);
+                    self.lower_struct_into(expr, dst);
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .insert(identifier.to_owned(), (local, ident.line));
+                    return;
+                }
+
+                let src = self.lower_expr(expr);
+                let ty = kind.as_ref().map_or(self.func.vreg_type(src), |kind| self.types.ir_type(kind));
+                let local = self.declare_local(identifier, ident.line, ty, None);
+                self.emit(IrInst::Store { local, src });
+            }
+            NodeStmt::LetArray { ident, kind, init } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                if let Some((_, line)) = self.lookup(identifier) {
+                    panic!("{} already defined at line {}", identifier, line);
+                }
+
+                let (kind, len) = kind.expect("Array is typed before lowering");
+                match init {
+                    NodeArray::Repeat { expr, .. } => {
+                        let src = self.lower_expr(expr);
+                        let local = self.declare_local(identifier, ident.line, self.types.ir_type(&kind), Some(len));
+                        self.emit(IrInst::Fill { local, src });
+                    }
+                    NodeArray::List(exprs) => {
+                        let values: Vec<VReg> = exprs.iter().map(|expr| self.lower_expr(expr)).collect();
+                        let local = self.declare_local(identifier, ident.line, self.types.ir_type(&kind), Some(len));
+                        for (position, src) in values.into_iter().enumerate() {
+                            let index = self.new_vreg(IrType::I64);
+                            self.emit(IrInst::Const { dst: index, value: position as i64 });
+                            self.emit(IrInst::StoreIndex { local, index, src });
+                        }
+                    }
+                }
+            }
+            NodeStmt::ReAssign { ident, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                let Some((local, _)) = self.lookup(identifier) else {
+                    let Some(&Global::Static(global, _)) = self.globals.get(identifier.as_str()) else {
+                        panic!("{} not declared but used in line {}", identifier, ident.line)
+                    };
+                    let src = self.lower_expr(expr);
+                    self.emit(IrInst::StoreGlobal { global, src });
+                    return;
+                };
+                if let IrType::Struct(_) | IrType::Ptr(_) = self.func.locals[local.0].ty {
+                    let dst = self.address(local);
+                    self.lower_struct_into(expr, dst);
+                    return;
+                }
+                let src = self.lower_expr(expr);
+                self.emit(IrInst::Store { local, src });
+            }
+            NodeStmt::AssignField { ident, fields, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                let (local, _) = self.lookup(identifier).unwrap_or_else(|| {
+                    panic!("{} not declared but used in line {}", identifier, ident.line)
+                });
+                let (last, path) = fields.split_last().expect("Parsed with at least one field");
+                let mut base = self.address(local);
+                for field in path {
+                    base = self.field_address(base, field);
+                }
+                let (ty, offset) = self.field(base, last);
+                match ty {
+                    IrType::Struct(_) => {
+                        let dst = self.field_address(base, last);
+                        self.lower_struct_into(expr, dst);
+                    }
+                    _ => {
+                        let src = self.lower_expr(expr);
+                        self.emit(IrInst::StoreField { base, offset, src });
+                    }
+                }
+            }
+            NodeStmt::AssignIndex { ident, index, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                let (local, _) = self.lookup(identifier).unwrap_or_else(|| {
+                    panic!("{} not declared but used in line {}", identifier, ident.line)
+                });
+                let index = self.lower_expr(index);
+                let src = self.lower_expr(expr);
+                self.emit(IrInst::StoreIndex { local, index, src });
+            }
+            NodeStmt::Return { expr } => {
+                let value = match (expr, self.ret_ptr) {
+                    (Some(expr), Some(ret_ptr)) => {
+                        let dst = self.address(ret_ptr);
+                        self.lower_struct_into(expr, dst);
+                        Some(dst)
+                    }
+                    (Some(expr), None) => Some(self.lower_expr(expr)),
+                    (None, _) => None,
+                };
+                self.terminate(Terminator::Ret(value));
+            }
+            NodeStmt::Print { expr, newline } => {
+                let src = self.lower_expr(expr);
+                self.emit(IrInst::Print { src, newline: *newline });
+            }
+            NodeStmt::Scope { scope } => {
+                self.lower_scope(scope);
+            }
+            NodeStmt::If { expr, scope, chain } => {
+                let end_block = self.new_block();
+                self.lower_if(expr, scope, chain, end_block);
+                self.switch_to(end_block);
+            }
+            NodeStmt::Match(node) => {
+                self.lower_match(node, |lowering, scope| lowering.lower_scope(scope));
+            }
+            NodeStmt::Expr { expr } => {
+                self.lower_expr(expr);
+            }
+        }
+    }
+
+    // Lowers one `if`/`else if` link of the chain, every branch ends up in `end_block`.
+    fn lower_if(&mut self, expr: &NodeExpr, scope: &NodeScope, chain: &Option<NodeElse>, end_block: BlockId) {
+        let cond = self.lower_expr(expr);
+        let then_block = self.new_block();
+        let else_block = match chain {
+            Some(_) => self.new_block(),
+            None => end_block,
+        };
+        self.terminate(Terminator::Branch { cond, then_block, else_block });
+
+        self.switch_to(then_block);
+        self.lower_scope(scope);
+        self.terminate(Terminator::Jump(end_block));
+
+        match chain {
+            Some(NodeElse::ElseIf { expr, scope, chain }) => {
+                self.switch_to(else_block);
+                self.lower_if(expr, scope, chain, end_block);
+            }
+            Some(NodeElse::Else(scope)) => {
+                self.switch_to(else_block);
+                self.lower_scope(scope);
+                self.terminate(Terminator::Jump(end_block));
+            }
+            None => {}
+        }
+    }
+
+    // Switches on the value, or on the tag of an enum holding values, to one block per arm.
+    // `lower_body` fills an arm in with the bindings of its pattern in scope.
+    fn lower_match<T>(&mut self, node: &NodeMatch<T>, mut lower_body: impl FnMut(&mut Self, &T)) {
+        let scrutinee = self.lower_expr(&node.expr);
+        let value = match self.func.vreg_type(scrutinee) {
+            IrType::Ptr(_) => {
+                let tag = self.new_vreg(IrType::U32);
+                self.emit(IrInst::LoadField { dst: tag, base: scrutinee, offset: 0 });
+                tag
+            }
+            _ => scrutinee,
+        };
+
+        let end_block = self.new_block();
+        let arm_blocks: Vec<BlockId> = node.arms.iter().map(|_| self.new_block()).collect();
+        let mut cases = vec![];
+        let mut default = None;
+        for (arm, block) in node.arms.iter().zip(arm_blocks.iter()) {
+            match &arm.pattern {
+                NodePattern::Wildcard => default = Some(*block),
+                NodePattern::Literal(term) => cases.push((case_value(term), *block)),
+                NodePattern::Variant { id, variant, .. } => cases.push((self.types.tag(*id, variant), *block)),
+            }
+        }
+        // Validation made the arms exhaustive, without `_` the last one takes whatever is left
+        let default = default.or_else(|| cases.pop().map(|(_, block)| block));
+        // Without any arm there is no value to match, e.g. an enum without variants
+        let default = default.unwrap_or_else(|| self.new_block());
+        self.terminate(Terminator::Switch { value, cases, default });
+        if node.arms.is_empty() {
+            self.switch_to(default);
+            self.terminate(Terminator::Unreachable);
+        }
+
+        for (arm, block) in node.arms.iter().zip(arm_blocks) {
+            self.switch_to(block);
+            self.scopes.push(HashMap::new());
+            if let NodePattern::Variant { variant, bindings, .. } = &arm.pattern {
+                for (index, binding) in bindings.iter().enumerate() {
+                    if let Some(ident) = binding {
+                        self.bind_field(scrutinee, &format!("{}.{}", variant, index), ident);
+                    }
+                }
+            }
+            lower_body(self, &arm.body);
+            self.scopes.pop();
+            self.terminate(Terminator::Jump(end_block));
+        }
+        self.switch_to(end_block);
+    }
+
+    // Declares `ident` holding a copy of a field of the enum at `base`
+    fn bind_field(&mut self, base: VReg, field: &str, ident: &TokenData) {
+        let identifier = cast!(&ident.token, Token::Indent);
+        if let Some((_, line)) = self.lookup(identifier) {
+            panic!("{} already defined at line {}", identifier, line);
+        }
+        let (ty, offset) = self.field(base, field);
+        match ty {
+            IrType::Struct(id) => {
+                let local = self.declare_local(identifier, ident.line, ty, None);
+                let dst = self.address(local);
+                let src = self.field_address(base, field);
+                self.emit(IrInst::Copy { dst, src, size: self.types.structs[id].size });
+            }
+            _ => {
+                let src = self.new_vreg(ty);
+                self.emit(IrInst::LoadField { dst: src, base, offset });
+                let local = self.declare_local(identifier, ident.line, ty, None);
+                self.emit(IrInst::Store { local, src });
+            }
+        }
+    }
+}
+
+// Bits of a literal pattern, the way the literal would be loaded
+fn case_value(term: &NodeTermExpr) -> i64 {
+    match term {
+        NodeTermExpr::IntLiteral(value, _) => {
+            let value: i128 = value.parse().expect("Integer literal is validated before lowering");
+            value as i64
+        }
+        NodeTermExpr::CharLiteral(value) => *value as i64,
+        NodeTermExpr::BooleanLiteral(value) => *value as i64,
+        _ => unreachable!("Patterns are integer, char or bool literals"),
+    }
+}
+
+// Expression
+
+impl<'a> FuncLowering<'a> {
+    fn lower_expr(&mut self, expr: &NodeExpr) -> VReg {
+        match expr {
+            NodeExpr::BinaryExpr(binary_expr) => {
+                let lhs = self.lower_expr(&binary_expr.lhs);
+                let rhs = self.lower_expr(&binary_expr.rhs);
+                // Validation made both operands the same type
+                let operand_type = self.func.vreg_type(lhs);
+                let (op, ty) = match binary_expr.op {
+                    NodeBiOp::Add => (BinaryOp::Add, operand_type),
+                    NodeBiOp::Subtract => (BinaryOp::Sub, operand_type),
+                    NodeBiOp::Multiply => (BinaryOp::Mul, operand_type),
+                    NodeBiOp::Division => (BinaryOp::Div, operand_type),
+                    NodeBiOp::Equality => (BinaryOp::Eq, IrType::Bool),
+                    NodeBiOp::LessThan => (BinaryOp::Lt, IrType::Bool),
+                    NodeBiOp::LessThanEqual => (BinaryOp::Le, IrType::Bool),
+                    NodeBiOp::GreaterThan => (BinaryOp::Gt, IrType::Bool),
+                    NodeBiOp::GreaterThanEqual => (BinaryOp::Ge, IrType::Bool),
+                    NodeBiOp::Or => (BinaryOp::Or, IrType::Bool),
+                    NodeBiOp::And => (BinaryOp::And, IrType::Bool),
+                };
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::Binary { dst, op, lhs, rhs });
+                dst
+            }
+            NodeExpr::Term(term) => self.lower_term(term),
+            NodeExpr::Call { name, args } => {
+                let ret_ptr = match self.signature(name, args).ret_type {
+                    IrType::Ptr(id) => Some(self.struct_temp(id)),
+                    _ => None,
+                };
+                self.lower_call(name, args, ret_ptr)
+            }
+            NodeExpr::Cast { expr, kind } => {
+                let src = self.lower_expr(expr);
+                let ty = self.types.ir_type(kind);
+                if self.func.vreg_type(src) == ty {
+                    return src;
+                }
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::Cast { dst, src });
+                dst
+            }
+            NodeExpr::Read(kind) => {
+                let dst = self.new_vreg(self.types.ir_type(kind));
+                self.emit(IrInst::Read { dst });
+                dst
+            }
+            NodeExpr::Index { name, index } => {
+                let (local, _) = self.lookup(name).unwrap_or_else(|| panic!("Undefined variable {}", name));
+                let index = self.lower_expr(index);
+                let dst = self.new_vreg(self.func.locals[local.0].ty);
+                self.emit(IrInst::LoadIndex { dst, local, index });
+                dst
+            }
+            NodeExpr::StructLiteral { id, .. } => {
+                let dst = self.struct_temp(*id);
+                self.lower_struct_into(expr, dst);
+                dst
+            }
+            NodeExpr::Variant { id, variant, .. } => match self.types.ir_type(&LitKind::Enum(*id)) {
+                IrType::Struct(layout) => {
+                    let dst = self.struct_temp(layout);
+                    self.lower_struct_into(expr, dst);
+                    dst
+                }
+                ty => {
+                    let dst = self.new_vreg(ty);
+                    self.emit(IrInst::Const { dst, value: self.types.tag(*id, variant) });
+                    dst
+                }
+            },
+            NodeExpr::Match { node, kind } => {
+                let kind = kind.expect("Match is typed before lowering");
+                match value_type(&kind, self.types) {
+                    IrType::Ptr(id) => {
+                        let dst = self.struct_temp(id);
+                        self.lower_struct_into(expr, dst);
+                        dst
+                    }
+                    ty => {
+                        // Every arm leaves its value in the same local
+                        let local = self.add_local("match", ty, None);
+                        self.lower_match(node, |lowering, expr| {
+                            let src = lowering.lower_expr(expr);
+                            lowering.emit(IrInst::Store { local, src });
+                        });
+                        let dst = self.new_vreg(ty);
+                        self.emit(IrInst::Load { dst, local });
+                        dst
+                    }
+                }
+            }
+            NodeExpr::Block(block) => self.lower_block(block, |lowering, expr| lowering.lower_expr(expr)),
+            NodeExpr::If { kind, .. } => {
+                let kind = kind.expect("If is typed before lowering");
+                match value_type(&kind, self.types) {
+                    IrType::Ptr(id) => {
+                        let dst = self.struct_temp(id);
+                        self.lower_struct_into(expr, dst);
+                        dst
+                    }
+                    ty => {
+                        // Both branches leave their value in the same local
+                        let local = self.add_local("if", ty, None);
+                        self.lower_if_value(expr, |lowering, expr| {
+                            let src = lowering.lower_expr(expr);
+                            lowering.emit(IrInst::Store { local, src });
+                        });
+                        let dst = self.new_vreg(ty);
+                        self.emit(IrInst::Load { dst, local });
+                        dst
+                    }
+                }
+            }
+            NodeExpr::Field { expr, field } => {
+                let base = self.lower_expr(expr);
+                let (ty, offset) = self.field(base, field);
+                if let IrType::Struct(_) = ty {
+                    return self.field_address(base, field);
+                }
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::LoadField { dst, base, offset });
+                dst
+            }
+        }
+    }
+
+    // The statements of a block in their own scope, `lower_value` gets its trailing expression
+    fn lower_block<T>(&mut self, block: &NodeBlock, lower_value: impl FnOnce(&mut Self, &NodeExpr) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        for stmt in block.stmts.iter() {
+            self.lower_stmt(stmt);
+        }
+        let value = lower_value(self, &block.expr);
+        self.scopes.pop();
+        value
+    }
+
+    // Branches on the conditions of an `if` and its `else if`s, `lower_value` gets the block of the branch taken
+    fn lower_if_value(&mut self, expr: &NodeExpr, mut lower_value: impl FnMut(&mut Self, &NodeExpr)) {
+        let end_block = self.new_block();
+        let mut expr = expr;
+        while let NodeExpr::If { cond, then, otherwise, .. } = expr {
+            let cond = self.lower_expr(cond);
+            let then_block = self.new_block();
+            let else_block = self.new_block();
+            self.terminate(Terminator::Branch { cond, then_block, else_block });
+
+            self.switch_to(then_block);
+            lower_value(self, then);
+            self.terminate(Terminator::Jump(end_block));
+            self.switch_to(else_block);
+            expr = otherwise;
+        }
+        lower_value(self, expr);
+        self.terminate(Terminator::Jump(end_block));
+        self.switch_to(end_block);
+    }
+
+    fn signature(&self, name: &str, args: &[NodeExpr]) -> &'a Signature {
+        let signature = self.signatures.get(name).unwrap_or_else(|| panic!("Undefined function {}", name));
+        if signature.params.len() != args.len() {
+            panic!("Function {} takes {} arguments but {} were given", name, signature.params.len(), args.len());
+        }
+        signature
+    }
+
+    // With `ret_ptr` the callee returns a struct by writing it there
+    fn lower_call(&mut self, name: &str, args: &[NodeExpr], ret_ptr: Option<VReg>) -> VReg {
+        let ty = self.signature(name, args).ret_type;
+        let mut values: Vec<VReg> = ret_ptr.into_iter().collect();
+        for arg in args {
+            let value = self.lower_expr(arg);
+            let value = match (self.func.vreg_type(value), arg) {
+                // A struct is passed by value, the callee gets the address of a copy nobody else sees
+                (IrType::Ptr(id), NodeExpr::Term(_) | NodeExpr::Field { .. } | NodeExpr::Block(_)) => {
+                    let dst = self.struct_temp(id);
+                    self.emit(IrInst::Copy { dst, src: value, size: self.types.structs[id].size });
+                    dst
+                }
+                _ => value,
+            };
+            values.push(value);
+        }
+        let dst = self.new_vreg(ty);
+        self.emit(IrInst::Call { dst, func: name.to_owned(), args: values });
+        dst
+    }
+
+    // Writes a struct valued expression to the struct at `dst`
+    fn lower_struct_into(&mut self, expr: &NodeExpr, dst: VReg) {
+        match expr {
+            NodeExpr::StructLiteral { fields, .. } => {
+                // Every field is set aside before any is written, `r = R { a: r.b, b: r.a }` reads dst
+                let values: Vec<VReg> = fields.iter().map(|(_, expr)| self.lower_struct_operand(expr)).collect();
+                for ((ident, _), src) in fields.iter().zip(values) {
+                    let field = cast!(&ident.token, Token::Indent);
+                    let (ty, offset) = self.field(dst, field);
+                    match ty {
+                        IrType::Struct(id) => {
+                            let field_dst = self.field_address(dst, field);
+                            self.emit(IrInst::Copy { dst: field_dst, src, size: self.types.structs[id].size });
+                        }
+                        _ => self.emit(IrInst::StoreField { base: dst, offset, src }),
+                    }
+                }
+            }
+            NodeExpr::Variant { id, variant, args } => {
+                // Like the fields of a struct literal, the payload is set aside before the tag is written
+                let values: Vec<VReg> = args.iter().map(|expr| self.lower_struct_operand(expr)).collect();
+                let tag = self.new_vreg(IrType::U32);
+                self.emit(IrInst::Const { dst: tag, value: self.types.tag(*id, variant) });
+                self.emit(IrInst::StoreField { base: dst, offset: 0, src: tag });
+                for (index, src) in values.into_iter().enumerate() {
+                    let field = format!("{}.{}", variant, index);
+                    let (ty, offset) = self.field(dst, &field);
+                    match ty {
+                        IrType::Struct(id) => {
+                            let field_dst = self.field_address(dst, &field);
+                            self.emit(IrInst::Copy { dst: field_dst, src, size: self.types.structs[id].size });
+                        }
+                        _ => self.emit(IrInst::StoreField { base: dst, offset, src }),
+                    }
+                }
+            }
+            NodeExpr::Match { node, .. } => {
+                self.lower_match(node, |lowering, expr| lowering.lower_struct_into(expr, dst));
+            }
+            NodeExpr::Block(block) => self.lower_block(block, |lowering, expr| lowering.lower_struct_into(expr, dst)),
+            NodeExpr::If { .. } => {
+                self.lower_if_value(expr, |lowering, expr| lowering.lower_struct_into(expr, dst));
+            }
+            NodeExpr::Call { name, args } => {
+                self.lower_call(name, args, Some(dst));
+            }
+            NodeExpr::Term(NodeTermExpr::Expression(expr)) => self.lower_struct_into(expr, dst),
+            _ => {
+                let src = self.lower_expr(expr);
+                let IrType::Ptr(id) = self.func.vreg_type(src) else { unreachable!("Validated as a struct") };
+                self.emit(IrInst::Copy { dst, src, size: self.types.structs[id].size });
+            }
+        }
+    }
+
+    // A field of a struct literal or variant. A struct read from a variable is copied aside, as it
+    // may be part of the destination
+    fn lower_struct_operand(&mut self, expr: &NodeExpr) -> VReg {
+        let value = self.lower_expr(expr);
+        match (self.func.vreg_type(value), expr) {
+            (IrType::Ptr(id), NodeExpr::Term(_) | NodeExpr::Field { .. } | NodeExpr::Block(_)) => {
+                let dst = self.struct_temp(id);
+                self.emit(IrInst::Copy { dst, src: value, size: self.types.structs[id].size });
+                dst
+            }
+            _ => value,
+        }
+    }
+
+    // Address of a fresh struct local
+    fn struct_temp(&mut self, id: usize) -> VReg {
+        let local = self.add_local(&format!("{}.tmp", self.types.structs[id].name), IrType::Struct(id), None);
+        self.address(local)
+    }
+
+    // Where a struct local is, a struct parameter holds the address of the caller's copy
+    fn address(&mut self, local: LocalId) -> VReg {
+        match self.func.locals[local.0].ty {
+            IrType::Struct(id) => {
+                let dst = self.new_vreg(IrType::Ptr(id));
+                self.emit(IrInst::Addr { dst, local });
+                dst
+            }
+            ty @ IrType::Ptr(_) => {
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::Load { dst, local });
+                dst
+            }
+            ty => unreachable!("{} is not a struct", ty),
+        }
+    }
+
+    // Type and offset of a field of the struct at `base`
+    fn field(&self, base: VReg, field: &str) -> (IrType, usize) {
+        let IrType::Ptr(id) = self.func.vreg_type(base) else { unreachable!("Validated as a struct") };
+        let field = self.types.structs[id].field(field);
+        (field.ty, field.offset)
+    }
+
+    fn field_address(&mut self, base: VReg, field: &str) -> VReg {
+        let (ty, offset) = self.field(base, field);
+        let IrType::Struct(id) = ty else { unreachable!("Only struct fields have their address taken") };
+        let dst = self.new_vreg(IrType::Ptr(id));
+        self.emit(IrInst::FieldAddr { dst, base, offset });
+        dst
+    }
+
+    fn lower_term(&mut self, term: &NodeTermExpr) -> VReg {
+        match term {
+            NodeTermExpr::IntLiteral(value, kind) => {
+                let value: i128 = value.parse().expect("Integer literal is validated before lowering");
+                let kind = kind.expect("Integer literal is typed before lowering");
+                let dst = self.new_vreg(self.types.ir_type(&kind));
+                // u64 values past i64::MAX keep their bits
+                self.emit(IrInst::Const { dst, value: value as i64 });
+                dst
+            }
+            NodeTermExpr::FloatLiteral(value) => {
+                let value: f64 = value.parse().expect("Float literal is lexed as digits");
+                let dst = self.new_vreg(IrType::F64);
+                self.emit(IrInst::Const { dst, value: value.to_bits() as i64 });
+                dst
+            }
+            NodeTermExpr::CharLiteral(value) => {
+                let dst = self.new_vreg(IrType::Char);
+                self.emit(IrInst::Const { dst, value: *value as i64 });
+                dst
+            }
+            NodeTermExpr::StringLiteral(value) => {
+                let index = match self.strings.iter().position(|string| string == value) {
+                    Some(index) => index,
+                    None => {
+                        self.strings.push(value.to_owned());
+                        self.strings.len() - 1
+                    }
+                };
+                let dst = self.new_vreg(IrType::Str);
+                self.emit(IrInst::Str { dst, index });
+                dst
+            }
+            NodeTermExpr::BooleanLiteral(value) => {
+                let dst = self.new_vreg(IrType::Bool);
+                self.emit(IrInst::Const { dst, value: *value as i64 });
+                dst
+            }
+            NodeTermExpr::Identifier(identifier) => {
+                let Some((local, _)) = self.lookup(identifier) else {
+                    return self.lower_global(identifier);
+                };
+                if let IrType::Struct(_) | IrType::Ptr(_) = self.func.locals[local.0].ty {
+                    return self.address(local);
+                }
+                let dst = self.new_vreg(self.func.locals[local.0].ty);
+                self.emit(IrInst::Load { dst, local });
+                dst
+            }
+            NodeTermExpr::Expression(expr) => self.lower_expr(expr),
+        }
+    }
+
+    // A name no local is declared with, constants are used by value
+    fn lower_global(&mut self, identifier: &str) -> VReg {
+        match self.globals.get(identifier) {
+            Some(&Global::Const(ty, value)) => {
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::Const { dst, value });
+                dst
+            }
+            Some(&Global::Static(global, ty)) => {
+                let dst = self.new_vreg(ty);
+                self.emit(IrInst::LoadGlobal { dst, global });
+                dst
+            }
+            None => panic!("Undefined variable {}", identifier),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt::{Display, Formatter, Result};
+
+use crate::parsing::InlineHint;
+
+use super::ir_structs::{
+    BinaryOp, BlockId, IrBlock, IrFunction, IrInst, IrModule, IrType, LocalId, Terminator, VReg,
+};
+
+// Textual form used by `--emit ir`:
+//
+// @0 = "string literal"
+// struct.0 = Point { x: i32 +0, y: i32 +4 }, 8 bytes
+// global.0 = COUNTER: i32 = 0
+//
+// fn add(%x: i32, %y: i32) -> i32 {
+//   %a: i32
+//   %xs: [i32; 3]
+//   %p: struct.0
+// bb0:
+//   v0: i32 = const 10
+//   store %a, v0
+//   store global.0, v0
+//   v1: ptr = addr %p
+//   store [v1 + 4], v0
+//   ret v0
+// }
+
+impl Display for IrModule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(f, "@{} = {:?}", index, string)?;
+        }
+        for (index, ir_struct) in self.structs.iter().enumerate() {
+            write!(f, "{} = {} {{ ", IrType::Struct(index), ir_struct.name)?;
+            for (position, field) in ir_struct.fields.iter().enumerate() {
+                if position > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {} +{}", field.name, field.ty, field.offset)?;
+            }
+            writeln!(f, " }}, {} bytes", ir_struct.size)?;
+        }
+        for (index, global) in self.globals.iter().enumerate() {
+            writeln!(f, "global.{} = {}: {} = {}", index, global.name, global.ty, global.ty.value_of(global.value))?;
+        }
+        for (index, func) in self.funcs.iter().enumerate() {
+            if index > 0 || !self.strings.is_empty() || !self.structs.is_empty() || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", func)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for IrFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self.inline {
+            Some(InlineHint::Always) => writeln!(f, "#[inline]")?,
+            Some(InlineHint::Never) => writeln!(f, "#[inline(never)]")?,
+            None => {}
+        }
+        let (params, locals) = self.locals.split_at(self.params);
+        write!(f, "fn {}(", self.name)?;
+        for (index, param) in params.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "%{}: {}", param.name, param.ty)?;
+        }
+        writeln!(f, ") -> {} {{", self.ret_type)?;
+        for local in locals.iter() {
+            match local.len {
+                Some(len) => writeln!(f, "  %{}: [{}; {}]", local.name, local.ty, len)?,
+                None => writeln!(f, "  %{}: {}", local.name, local.ty)?,
+            }
+        }
+        for block in self.blocks.iter() {
+            write!(f, "{}", FuncItem(self, block))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+// Instructions only know their locals by id, so they are printed through their function
+struct FuncItem<'a, T>(&'a IrFunction, &'a T);
+
+impl Display for FuncItem<'_, IrBlock> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let FuncItem(func, block) = self;
+        writeln!(f, "{}:", block.id)?;
+        for inst in block.insts.iter() {
+            writeln!(f, "  {}", FuncItem(func, inst))?;
+        }
+        writeln!(f, "  {}", block.terminator)
+    }
+}
+
+impl Display for FuncItem<'_, IrInst> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let FuncItem(func, inst) = self;
+        let local = |local: &LocalId| &func.locals[local.0].name;
+        match inst {
+            IrInst::Const { dst, value } => match func.vreg_type(*dst) {
+                IrType::F64 => write!(f, "{}: f64 = const {:?}", dst, f64::from_bits(*value as u64)),
+                ty => write!(f, "{}: {} = const {}", dst, ty, ty.value_of(*value)),
+            },
+            IrInst::Binary { dst, op, lhs, rhs } => {
+                write!(f, "{}: {} = {} {}, {}", dst, func.vreg_type(*dst), op, lhs, rhs)
+            }
+            IrInst::Load { dst, local: id } => {
+                write!(f, "{}: {} = load %{}", dst, func.vreg_type(*dst), local(id))
+            }
+            IrInst::Store { local: id, src } => write!(f, "store %{}, {}", local(id), src),
+            IrInst::LoadGlobal { dst, global } => {
+                write!(f, "{}: {} = load global.{}", dst, func.vreg_type(*dst), global)
+            }
+            IrInst::StoreGlobal { global, src } => write!(f, "store global.{}, {}", global, src),
+            IrInst::LoadIndex { dst, local: id, index } => {
+                write!(f, "{}: {} = load %{}[{}]", dst, func.vreg_type(*dst), local(id), index)
+            }
+            IrInst::StoreIndex { local: id, index, src } => write!(f, "store %{}[{}], {}", local(id), index, src),
+            IrInst::Fill { local: id, src } => write!(f, "fill %{}, {}", local(id), src),
+            IrInst::Call { dst, func: callee, args } => {
+                write!(f, "{}: {} = call {}({})", dst, func.vreg_type(*dst), callee, Args(args))
+            }
+            IrInst::Cast { dst, src } => write!(f, "{}: {} = cast {}", dst, func.vreg_type(*dst), src),
+            IrInst::Str { dst, index } => write!(f, "{}: str = string @{}", dst, index),
+            IrInst::Print { src, newline: false } => write!(f, "print {}", src),
+            IrInst::Print { src, newline: true } => write!(f, "println {}", src),
+            IrInst::Read { dst } => write!(f, "{}: {} = read", dst, func.vreg_type(*dst)),
+            IrInst::Addr { dst, local: id } => write!(f, "{}: ptr = addr %{}", dst, local(id)),
+            IrInst::FieldAddr { dst, base, offset } => write!(f, "{}: ptr = addr [{} + {}]", dst, base, offset),
+            IrInst::LoadField { dst, base, offset } => {
+                write!(f, "{}: {} = load [{} + {}]", dst, func.vreg_type(*dst), base, offset)
+            }
+            IrInst::StoreField { base, offset, src } => write!(f, "store [{} + {}], {}", base, offset, src),
+            IrInst::Copy { dst, src, size } => write!(f, "copy {}, {}, {}", dst, src, size),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Terminator::Ret(Some(value)) => write!(f, "ret {}", value),
+            Terminator::Ret(None) => write!(f, "ret"),
+            Terminator::Jump(target) => write!(f, "jmp {}", target),
+            Terminator::Branch { cond, then_block, else_block } => {
+                write!(f, "br {}, {}, {}", cond, then_block, else_block)
+            }
+            Terminator::Switch { value, cases, default } => {
+                let cases: Vec<String> = cases.iter().map(|(case, target)| format!("{}: {}", case, target)).collect();
+                write!(f, "switch {}, [{}], {}", value, cases.join(", "), default)
+            }
+            Terminator::TailCall { func, args } => write!(f, "tailcall {}({})", func, Args(args)),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+// `v1, v2`
+struct Args<'a>(&'a [VReg]);
+
+impl Display for Args<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (index, arg) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for VReg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for IrType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            IrType::I32 => write!(f, "i32"),
+            IrType::I64 => write!(f, "i64"),
+            IrType::U8 => write!(f, "u8"),
+            IrType::U32 => write!(f, "u32"),
+            IrType::U64 => write!(f, "u64"),
+            IrType::F64 => write!(f, "f64"),
+            IrType::Char => write!(f, "char"),
+            IrType::Str => write!(f, "str"),
+            IrType::Bool => write!(f, "bool"),
+            IrType::Ptr(_) => write!(f, "ptr"),
+            IrType::Struct(id) => write!(f, "struct.{}", id),
+            IrType::Unit => write!(f, "unit"),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Eq => "eq",
+            BinaryOp::Lt => "lt",
+            BinaryOp::Le => "le",
+            BinaryOp::Gt => "gt",
+            BinaryOp::Ge => "ge",
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use crate::{lexical::LitKind, parsing::InlineHint};
+
+/// Virtual register. Every register is assigned exactly once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VReg(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+/// Stack slot backing a source level variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LocalId(pub usize);
+
+/// Values live in 64-bit registers, kept sign or zero extended from their width.
+/// An f64 is kept as its bit pattern, a str as the address of its length
+/// followed by its bytes. Structs never live in registers, only their address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrType {
+    I32,
+    I64,
+    U8,
+    U32,
+    U64,
+    F64,
+    Bool,
+    Char,
+    Str,
+    /// Address of a struct, indexing `IrModule::structs`
+    Ptr(usize),
+    /// A struct held in the frame, only the type of locals and fields
+    Struct(usize),
+    /// What a call to a function without a return type gives, nothing reads it
+    Unit,
+}
+
+impl IrType {
+    pub fn is_signed(&self) -> bool {
+        matches!(self, IrType::I32 | IrType::I64)
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            IrType::Bool | IrType::U8 => 8,
+            IrType::I32 | IrType::U32 | IrType::Char => 32,
+            IrType::I64 | IrType::U64 | IrType::F64 | IrType::Str | IrType::Ptr(_) => 64,
+            IrType::Struct(_) => unreachable!("A struct does not fit a register"),
+            IrType::Unit => unreachable!("Unit has no value to hold"),
+        }
+    }
+
+    /// Smallest and largest value of an integer type, bool or char.
+    pub fn range(&self) -> (i128, i128) {
+        match self {
+            IrType::I32 => (i32::MIN as i128, i32::MAX as i128),
+            IrType::I64 => (i64::MIN as i128, i64::MAX as i128),
+            IrType::U8 => (0, u8::MAX as i128),
+            IrType::U32 => (0, u32::MAX as i128),
+            IrType::U64 => (0, u64::MAX as i128),
+            IrType::Bool => (0, 1),
+            IrType::Char => (0, char::MAX as i128),
+            IrType::F64 | IrType::Str | IrType::Ptr(_) | IrType::Struct(_) | IrType::Unit => {
+                unreachable!("{} has no integer range", self)
+            }
+        }
+    }
+
+    /// `value` truncated to the width of the type, the way a cast does.
+    pub fn wrap(&self, value: i128) -> i64 {
+        let (min, max) = self.range();
+        ((value - min).rem_euclid(max - min + 1) + min) as i64
+    }
+
+    /// `value` read back as a number of this type, u64 constants are kept in an i64.
+    pub fn value_of(&self, value: i64) -> i128 {
+        match self {
+            IrType::U64 => value as u64 as i128,
+            _ => value as i128,
+        }
+    }
+}
+
+impl From<&LitKind> for IrType {
+    fn from(kind: &LitKind) -> Self {
+        match kind {
+            LitKind::I32 => IrType::I32,
+            LitKind::I64 => IrType::I64,
+            LitKind::U8 => IrType::U8,
+            LitKind::U32 => IrType::U32,
+            LitKind::U64 => IrType::U64,
+            LitKind::F64 => IrType::F64,
+            LitKind::Char => IrType::Char,
+            LitKind::Str => IrType::Str,
+            LitKind::Bool => IrType::Bool,
+            LitKind::Struct(id) => IrType::Struct(*id),
+            LitKind::Enum(_) => unreachable!("Enums are typed through their layout"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IrModule {
+    pub funcs: Vec<IrFunction>,
+    /// String literals, each stored once
+    pub strings: Vec<String>,
+    /// Layout of every struct, `IrType::Struct` and `IrType::Ptr` index into it
+    pub structs: Vec<IrStruct>,
+    /// Statics, constants are replaced by their value while lowering
+    pub globals: Vec<IrGlobal>,
+}
+
+/// `static mut COUNTER: i32 = 0;`, stored in the data section
+#[derive(Debug, Clone)]
+pub struct IrGlobal {
+    pub name: String,
+    pub ty: IrType,
+    /// The bits of the initial value, see [`IrType`]
+    pub value: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IrStruct {
+    pub name: String,
+    pub fields: Vec<IrField>,
+    /// Bytes taken, a multiple of `align`
+    pub size: usize,
+    pub align: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct IrField {
+    pub name: String,
+    pub ty: IrType,
+    /// Bytes from the start of the struct
+    pub offset: usize,
+}
+
+impl IrStruct {
+    pub fn field(&self, name: &str) -> &IrField {
+        self.fields.iter().find(|field| field.name == name).expect("Fields are validated before lowering")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IrFunction {
+    pub name: String,
+    pub ret_type: IrType,
+    pub inline: Option<InlineHint>,
+    /// The first `params` locals hold the arguments, in order
+    pub params: usize,
+    pub locals: Vec<IrLocal>,
+    /// Type of every virtual register, indexed by `VReg.0`
+    pub vregs: Vec<IrType>,
+    /// `blocks[0]` is the entry block
+    pub blocks: Vec<IrBlock>,
+}
+
+impl IrFunction {
+    pub fn vreg_type(&self, vreg: VReg) -> IrType {
+        self.vregs[vreg.0]
+    }
+
+    /// Gives every block its position as id, after blocks were moved, added or removed.
+    pub fn renumber_blocks(&mut self) {
+        let order: HashMap<BlockId, BlockId> =
+            self.blocks.iter().enumerate().map(|(index, block)| (block.id, BlockId(index))).collect();
+        for block in self.blocks.iter_mut() {
+            block.id = order[&block.id];
+            block.terminator.map_targets(|target| order[&target]);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IrLocal {
+    pub name: String,
+    /// Type of the value, or of every element of an array
+    pub ty: IrType,
+    /// Number of elements of an array, which lives in consecutive stack slots
+    pub len: Option<usize>,
+}
+
+impl IrLocal {
+    /// Stack slots of an array or a struct, which always live in memory.
+    pub fn slots(&self, structs: &[IrStruct]) -> Option<usize> {
+        match (self.len, self.ty) {
+            (Some(len), _) => Some(len),
+            (None, IrType::Struct(id)) => Some(structs[id].size.div_ceil(8)),
+            (None, _) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IrBlock {
+    pub id: BlockId,
+    pub insts: Vec<IrInst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add, Sub, Mul, Div,
+    Eq, Lt, Le, Gt, Ge,
+    Or, And,
+}
+
+#[derive(Debug, Clone)]
+pub enum IrInst {
+    /// The bits of the value, see [`IrType`]
+    Const { dst: VReg, value: i64 },
+    Binary { dst: VReg, op: BinaryOp, lhs: VReg, rhs: VReg },
+    Load { dst: VReg, local: LocalId },
+    Store { local: LocalId, src: VReg },
+    /// Reads `globals[global]` of the module
+    LoadGlobal { dst: VReg, global: usize },
+    StoreGlobal { global: usize, src: VReg },
+    /// Reads element `index` of an array
+    LoadIndex { dst: VReg, local: LocalId, index: VReg },
+    StoreIndex { local: LocalId, index: VReg, src: VReg },
+    /// Writes `src` to every element of an array
+    Fill { local: LocalId, src: VReg },
+    /// Address of a struct local
+    Addr { dst: VReg, local: LocalId },
+    /// `base + offset`, the address of a field holding a struct
+    FieldAddr { dst: VReg, base: VReg, offset: usize },
+    /// Reads the field `offset` bytes past `base`, as wide as the type of `dst`
+    LoadField { dst: VReg, base: VReg, offset: usize },
+    StoreField { base: VReg, offset: usize, src: VReg },
+    /// Copies `size` bytes from the struct at `src` to the one at `dst`
+    Copy { dst: VReg, src: VReg, size: usize },
+    Call { dst: VReg, func: String, args: Vec<VReg> },
+    /// Converts `src` to the type of `dst`
+    Cast { dst: VReg, src: VReg },
+    /// Address of `strings[index]` of the module
+    Str { dst: VReg, index: usize },
+    /// Writes `src` to stdout through the runtime
+    Print { src: VReg, newline: bool },
+    /// Reads stdin through the runtime, an integer or a line by the type of `dst`
+    Read { dst: VReg },
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    /// Without a value in functions without a return type
+    Ret(Option<VReg>),
+    Jump(BlockId),
+    Branch { cond: VReg, then_block: BlockId, else_block: BlockId },
+    /// Goes to the block of the case equal to `value`, to `default` when none is
+    Switch { value: VReg, cases: Vec<(i64, BlockId)>, default: BlockId },
+    /// `return func(args)` reusing the caller's frame
+    TailCall { func: String, args: Vec<VReg> },
+    /// End of a block control never reaches, e.g. code after `return`
+    Unreachable,
+}
+
+impl IrInst {
+    /// Register written by the instruction, stores write memory instead.
+    pub fn dst(&self) -> Option<VReg> {
+        match self {
+            IrInst::Const { dst, .. }
+            | IrInst::Binary { dst, .. }
+            | IrInst::Load { dst, .. }
+            | IrInst::LoadGlobal { dst, .. }
+            | IrInst::LoadIndex { dst, .. }
+            | IrInst::Addr { dst, .. }
+            | IrInst::FieldAddr { dst, .. }
+            | IrInst::LoadField { dst, .. }
+            | IrInst::Call { dst, .. }
+            | IrInst::Cast { dst, .. }
+            | IrInst::Str { dst, .. }
+            | IrInst::Read { dst } => Some(*dst),
+            IrInst::Store { .. }
+            | IrInst::StoreGlobal { .. }
+            | IrInst::StoreIndex { .. }
+            | IrInst::Fill { .. }
+            | IrInst::StoreField { .. }
+            | IrInst::Copy { .. }
+            | IrInst::Print { .. } => None,
+        }
+    }
+
+    /// Local the instruction reads or writes.
+    pub fn local_mut(&mut self) -> Option<&mut LocalId> {
+        match self {
+            IrInst::Load { local, .. }
+            | IrInst::Store { local, .. }
+            | IrInst::LoadIndex { local, .. }
+            | IrInst::StoreIndex { local, .. }
+            | IrInst::Fill { local, .. }
+            | IrInst::Addr { local, .. } => Some(local),
+            _ => None,
+        }
+    }
+
+    /// Calls into functions or the runtime, which clobber caller saved registers.
+    pub fn is_call(&self) -> bool {
+        matches!(self, IrInst::Call { .. } | IrInst::Print { .. } | IrInst::Read { .. })
+    }
+
+    pub fn operands(&self) -> Vec<VReg> {
+        match self {
+            IrInst::Const { .. }
+            | IrInst::Load { .. }
+            | IrInst::LoadGlobal { .. }
+            | IrInst::Str { .. }
+            | IrInst::Read { .. }
+            | IrInst::Addr { .. } => vec![],
+            IrInst::FieldAddr { base, .. } | IrInst::LoadField { base, .. } => vec![*base],
+            IrInst::StoreField { base, src, .. } => vec![*base, *src],
+            IrInst::Copy { dst, src, .. } => vec![*dst, *src],
+            IrInst::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            IrInst::Store { src, .. }
+            | IrInst::StoreGlobal { src, .. }
+            | IrInst::Cast { src, .. }
+            | IrInst::Print { src, .. }
+            | IrInst::Fill { src, .. } => vec![*src],
+            IrInst::LoadIndex { index, .. } => vec![*index],
+            IrInst::StoreIndex { index, src, .. } => vec![*index, *src],
+            IrInst::Call { args, .. } => args.clone(),
+        }
+    }
+
+    /// Rewrites every register the instruction reads or writes.
+    pub fn map_vregs(&mut self, remap: impl Fn(VReg) -> VReg) {
+        match self {
+            IrInst::Const { dst, .. }
+            | IrInst::Load { dst, .. }
+            | IrInst::LoadGlobal { dst, .. }
+            | IrInst::Str { dst, .. }
+            | IrInst::Read { dst }
+            | IrInst::Addr { dst, .. } => *dst = remap(*dst),
+            IrInst::FieldAddr { dst, base, .. } | IrInst::LoadField { dst, base, .. } => {
+                *dst = remap(*dst);
+                *base = remap(*base);
+            }
+            IrInst::StoreField { base, src, .. } => {
+                *base = remap(*base);
+                *src = remap(*src);
+            }
+            IrInst::Copy { dst, src, .. } => {
+                *dst = remap(*dst);
+                *src = remap(*src);
+            }
+            IrInst::Binary { dst, lhs, rhs, .. } => {
+                *dst = remap(*dst);
+                *lhs = remap(*lhs);
+                *rhs = remap(*rhs);
+            }
+            IrInst::Store { src, .. }
+            | IrInst::StoreGlobal { src, .. }
+            | IrInst::Print { src, .. }
+            | IrInst::Fill { src, .. } => *src = remap(*src),
+            IrInst::LoadIndex { dst, index, .. } => {
+                *dst = remap(*dst);
+                *index = remap(*index);
+            }
+            IrInst::StoreIndex { index, src, .. } => {
+                *index = remap(*index);
+                *src = remap(*src);
+            }
+            IrInst::Cast { dst, src } => {
+                *dst = remap(*dst);
+                *src = remap(*src);
+            }
+            IrInst::Call { dst, args, .. } => {
+                *dst = remap(*dst);
+                args.iter_mut().for_each(|arg| *arg = remap(*arg));
+            }
+        }
+    }
+}
+
+impl Terminator {
+    pub fn operands(&self) -> Vec<VReg> {
+        match self {
+            Terminator::Ret(value) => value.iter().copied().collect(),
+            Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } => vec![*value],
+            Terminator::TailCall { args, .. } => args.clone(),
+            Terminator::Jump(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter().map(|(_, target)| *target).chain([*default]).collect()
+            }
+            Terminator::Ret(_) | Terminator::TailCall { .. } | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn map_targets(&mut self, remap: impl Fn(BlockId) -> BlockId) {
+        match self {
+            Terminator::Jump(target) => *target = remap(*target),
+            Terminator::Branch { then_block, else_block, .. } => {
+                *then_block = remap(*then_block);
+                *else_block = remap(*else_block);
+            }
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter_mut().for_each(|(_, target)| *target = remap(*target));
+                *default = remap(*default);
+            }
+            Terminator::Ret(_) | Terminator::TailCall { .. } | Terminator::Unreachable => {}
+        }
+    }
+
+    pub fn map_vregs(&mut self, remap: impl Fn(VReg) -> VReg) {
+        match self {
+            Terminator::Ret(value) => value.iter_mut().for_each(|value| *value = remap(*value)),
+            Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } => *value = remap(*value),
+            Terminator::TailCall { args, .. } => args.iter_mut().for_each(|arg| *arg = remap(*arg)),
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+}
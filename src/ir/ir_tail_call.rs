@@ -0,0 +1,24 @@
+use super::ir_structs::{IrInst, IrModule, IrType, Terminator};
+
+/// Turns `v = call f(...); ret v` into a tail call, which reuses the caller's
+/// frame instead of growing the stack. `main` is left alone, it exits the
+/// process instead of returning. So are calls passing a struct, its copy
+/// lives in the frame being reused.
+pub fn mark_tail_calls(module: &mut IrModule) {
+    for func in module.funcs.iter_mut().filter(|func| func.name != "main") {
+        for block in func.blocks.iter_mut() {
+            let Terminator::Ret(value) = block.terminator else { continue };
+            let Some(IrInst::Call { dst, args, .. }) = block.insts.last() else { continue };
+            // A call without a value is returned by a plain `ret`
+            let returns_call = match value {
+                Some(value) => *dst == value,
+                None => func.vregs[dst.0] == IrType::Unit,
+            };
+            if !returns_call || args.iter().any(|arg| matches!(func.vregs[arg.0], IrType::Ptr(_))) {
+                continue;
+            }
+            let Some(IrInst::Call { func, args, .. }) = block.insts.pop() else { unreachable!() };
+            block.terminator = Terminator::TailCall { func, args };
+        }
+    }
+}
@@ -7,16 +7,88 @@ pub struct TokenData {
     pub line: i32,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LitKind {
-    Integer,
+    I32,
+    I64,
+    U8,
+    U32,
+    U64,
+    F64,
     Bool,
+    Char,
+    /// Length and bytes of a string literal
+    Str,
+    /// Index of a `struct` declaration in the file
+    Struct(usize),
+    /// Index of an `enum` declaration in the file
+    Enum(usize),
+}
+
+impl LitKind {
+    pub fn from_name(name: &str) -> Option<LitKind> {
+        match name {
+            "i32" => Some(LitKind::I32),
+            "i64" => Some(LitKind::I64),
+            "u8" => Some(LitKind::U8),
+            "u32" => Some(LitKind::U32),
+            "u64" => Some(LitKind::U64),
+            "f64" => Some(LitKind::F64),
+            "bool" => Some(LitKind::Bool),
+            "char" => Some(LitKind::Char),
+            "str" => Some(LitKind::Str),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LitKind::I32 => "i32",
+            LitKind::I64 => "i64",
+            LitKind::U8 => "u8",
+            LitKind::U32 => "u32",
+            LitKind::U64 => "u64",
+            LitKind::F64 => "f64",
+            LitKind::Bool => "bool",
+            LitKind::Char => "char",
+            LitKind::Str => "str",
+            // Only the declaration knows the name
+            LitKind::Struct(_) => "struct",
+            LitKind::Enum(_) => "enum",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !matches!(
+            self,
+            LitKind::F64 | LitKind::Bool | LitKind::Char | LitKind::Str | LitKind::Struct(_) | LitKind::Enum(_)
+        )
+    }
+
+    /// Smallest and largest value of an integer type, bool or char.
+    pub fn range(&self) -> (i128, i128) {
+        match self {
+            LitKind::I32 => (i32::MIN as i128, i32::MAX as i128),
+            LitKind::I64 => (i64::MIN as i128, i64::MAX as i128),
+            LitKind::U8 => (0, u8::MAX as i128),
+            LitKind::U32 => (0, u32::MAX as i128),
+            LitKind::U64 => (0, u64::MAX as i128),
+            LitKind::Bool => (0, 1),
+            LitKind::Char => (0, char::MAX as i128),
+            LitKind::F64 | LitKind::Str | LitKind::Struct(_) | LitKind::Enum(_) => {
+                unreachable!("{} has no integer range", self.name())
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Token {
     Exit,
-    IntLiteral(String),
+    IntLiteral(String, Option<LitKind>), // decimal digits, also for `0xFF` and `1_000`, and the suffix of `10u8`
+    FloatLiteral(String), // `3.14`, `1e-3`, `2f64`
+    CharLiteral(char), // 'a'
+    StringLiteral(String), // "text", escapes resolved
     BooleanLiteral(bool),
     SemiColon,
     Let,
@@ -40,9 +112,27 @@ pub enum Token {
     GreaterThanEqual, // >=
     LessThan, // <
     LessThanEqual, // <=
-    OR, // ||
-    AND, // &&
+    Or, // ||
+    And, // &&
     LitType(LitKind),
+    Hash, // #
+    OpenSquare, // [
+    CloseSquare, // ]
+    Colon, // :
+    Comma, // ,
+    As, // as
+    Print(bool), // print, println
+    Read(LitKind), // read_int, read_line
+    Struct, // struct
+    Dot, // .
+    Enum, // enum
+    Match, // match
+    FatArrow, // =>
+    PathSep, // ::
+    Underscore, // _
+    Const, // const
+    Static, // static
+    Mut, // mut
 } 
 
 pub fn tokenize(content: &str) -> Vec<TokenData>{
@@ -53,10 +143,10 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
     let mut line_count = 1;
 
     while let Some(char) = chars.pop_front() {
-        if char.is_alphabetic() {
+        if char.is_alphabetic() || char == '_' {
             buffer.push(char);
             while let Some(element) = chars.front() {
-                if element.is_ascii_alphanumeric() {
+                if element.is_ascii_alphanumeric() || *element == '_' {
                     buffer.push(chars.pop_front().unwrap());    
                 } else {
                     break;
@@ -87,12 +177,54 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
                 tokens.push(TokenData { token: Token::Else, line: line_count });
                 buffer.clear();
             }
-            else if temp == "i32" {
-                tokens.push(TokenData { token: Token::LitType(LitKind::Integer), line: line_count });
+            else if temp == "true" || temp == "false" {
+                let value = temp == "true";
+                tokens.push(TokenData { token: Token::BooleanLiteral(value), line: line_count });
+                buffer.clear();
+            }
+            else if let Some(kind) = LitKind::from_name(&temp) {
+                tokens.push(TokenData { token: Token::LitType(kind), line: line_count });
+                buffer.clear();
+            }
+            else if temp == "as" {
+                tokens.push(TokenData { token: Token::As, line: line_count });
                 buffer.clear();
             }
-            else if temp == "bool" {
-                tokens.push(TokenData { token: Token::LitType(LitKind::Bool), line: line_count });
+            else if temp == "print" || temp == "println" {
+                tokens.push(TokenData { token: Token::Print(temp == "println"), line: line_count });
+                buffer.clear();
+            }
+            else if temp == "read_int" || temp == "read_line" {
+                let kind = if temp == "read_int" { LitKind::I64 } else { LitKind::Str };
+                tokens.push(TokenData { token: Token::Read(kind), line: line_count });
+                buffer.clear();
+            }
+            else if temp == "struct" {
+                tokens.push(TokenData { token: Token::Struct, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "enum" {
+                tokens.push(TokenData { token: Token::Enum, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "match" {
+                tokens.push(TokenData { token: Token::Match, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "const" {
+                tokens.push(TokenData { token: Token::Const, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "static" {
+                tokens.push(TokenData { token: Token::Static, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "mut" {
+                tokens.push(TokenData { token: Token::Mut, line: line_count });
+                buffer.clear();
+            }
+            else if temp == "_" {
+                tokens.push(TokenData { token: Token::Underscore, line: line_count });
                 buffer.clear();
             }
             else if temp == "fn" {
@@ -106,34 +238,114 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
         }
         else if char.is_ascii_digit() {
             if char.is_ascii_digit() {
-                buffer.push(char);
+                let radix = match chars.front() {
+                    Some('x') if char == '0' => 16,
+                    Some('o') if char == '0' => 8,
+                    Some('b') if char == '0' => 2,
+                    _ => 10,
+                };
+                if radix == 10 {
+                    buffer.push(char);
+                } else {
+                    chars.pop_front();
+                }
+                // `_` separates digits anywhere after the first one
                 while let Some(element) = chars.front() {
-                    if element.is_ascii_digit() {
+                    if element.is_digit(radix) || *element == '_' {
                         buffer.push(chars.pop_front().unwrap());    
                     } else {
                         break;
                     }
                 }
-                let temp: String = buffer.iter().collect();
-                if temp == "true" || temp == "false" {
-                    let value = if temp == "true" {
-                        true
-                    } else {
-                        false
-                    };
-                    tokens.push(TokenData { token: Token::BooleanLiteral(value), line: line_count });
-                } else {
-                    tokens.push(TokenData { token: Token::IntLiteral(temp), line: line_count });
+                // Fraction and exponent of a float, `1.` and `1.max` are not floats
+                let is_fraction =
+                    radix == 10 && is_next(&chars, '.') && chars.get(1).is_some_and(char::is_ascii_digit);
+                if is_fraction {
+                    buffer.push(chars.pop_front().unwrap());
+                    while chars.front().is_some_and(|element| element.is_ascii_digit() || *element == '_') {
+                        buffer.push(chars.pop_front().unwrap());
+                    }
                 }
+                let exponent_digits = if peek(&chars, '-', 1) || peek(&chars, '+', 1) { 2 } else { 1 };
+                let is_exponent = radix == 10
+                    && (is_next(&chars, 'e') || is_next(&chars, 'E'))
+                    && chars.get(exponent_digits).is_some_and(char::is_ascii_digit);
+                if is_exponent {
+                    for _ in 0..exponent_digits {
+                        buffer.push(chars.pop_front().unwrap());
+                    }
+                    while chars.front().is_some_and(char::is_ascii_digit) {
+                        buffer.push(chars.pop_front().unwrap());
+                    }
+                }
+                let digits: String = buffer.iter().filter(|digit| **digit != '_').collect();
                 buffer.clear();
+                // Later stages only see decimal digits
+                let temp = match radix {
+                    10 => digits,
+                    radix => match u128::from_str_radix(&digits, radix) {
+                        Ok(value) => value.to_string(),
+                        Err(_) => panic!("Invalid integer literal with radix {} at line {}", radix, line_count),
+                    },
+                };
+
+                let mut suffix = String::new();
+                while let Some(element) = chars.front() {
+                    if element.is_ascii_alphanumeric() {
+                        suffix.push(chars.pop_front().unwrap());
+                    } else {
+                        break;
+                    }
+                }
+                let kind = match LitKind::from_name(&suffix) {
+                    _ if suffix.is_empty() => None,
+                    Some(kind) if kind.is_integer() && !is_fraction && !is_exponent => Some(kind),
+                    Some(LitKind::F64) if radix == 10 => Some(LitKind::F64),
+                    _ => panic!("Invalid suffix {} on numeric literal {} at line {}", suffix, temp, line_count),
+                };
+                let token = match kind {
+                    Some(LitKind::F64) => Token::FloatLiteral(temp),
+                    _ if is_fraction || is_exponent => Token::FloatLiteral(temp),
+                    kind => Token::IntLiteral(temp, kind),
+                };
+                tokens.push(TokenData { token, line: line_count });
+            }
+        }
+        else if char == '\'' {
+            let value = match chars.pop_front() {
+                Some('\\') => escape(&mut chars, line_count),
+                Some(value) if value != '\'' && value != '\n' => value,
+                _ => panic!("Empty character literal at line {}", line_count),
+            };
+            if chars.pop_front() != Some('\'') {
+                panic!("Expected ' to close the character literal at line {}", line_count);
+            }
+            tokens.push(TokenData { token: Token::CharLiteral(value), line: line_count });
+        }
+        else if char == '"' {
+            // Strings may span lines, the token is on the line they start
+            let start = line_count;
+            let mut value = String::new();
+            loop {
+                match chars.pop_front() {
+                    Some('"') => break,
+                    Some('\\') => value.push(escape(&mut chars, line_count)),
+                    Some(element) => {
+                        if element == '\n' {
+                            line_count += 1;
+                        }
+                        value.push(element);
+                    }
+                    None => panic!("Unterminated string literal starting at line {}", start),
+                }
             }
+            tokens.push(TokenData { token: Token::StringLiteral(value), line: start });
         }
         else if char == '<' && is_next(&chars, '=') {
             chars.pop_front();
             tokens.push(TokenData { token: Token::LessThanEqual, line: line_count });
         }
         else if char == '<' {
-            chars.pop_front();
             tokens.push(TokenData { token: Token::LessThan, line: line_count });
         }
         else if char == '>' && is_next(&chars, '=') {
@@ -141,7 +353,6 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
             tokens.push(TokenData { token: Token::GreaterThanEqual, line: line_count });
         }
         else if char == '>' {
-            chars.pop_front();
             tokens.push(TokenData { token: Token::GreaterThan, line: line_count });
         }
         else if char == '-' && is_next(&chars, '>') {
@@ -160,20 +371,28 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
         }
         else if char == '|' && is_next(&chars, '|') {
             chars.pop_front();
-            tokens.push(TokenData { token: Token::OR, line: line_count });
+            tokens.push(TokenData { token: Token::Or, line: line_count });
         }
         else if char == '&' && is_next(&chars, '&') {
             chars.pop_front();
-            tokens.push(TokenData { token: Token::AND, line: line_count });
+            tokens.push(TokenData { token: Token::And, line: line_count });
         }
         else if char == '/' && is_next(&chars, '*') {
             chars.pop_front();
-            while !chars.is_empty() && !(is_next(&chars, '*') && peek(&chars, '/', 1)) {
+            while !(chars.is_empty() || is_next(&chars, '*') && peek(&chars, '/', 1)) {
                 chars.pop_front();
             }
             chars.pop_front();
             chars.pop_front();
         }
+        else if char == '=' && is_next(&chars, '>') {
+            chars.pop_front();
+            tokens.push(TokenData { token: Token::FatArrow, line: line_count });
+        }
+        else if char == ':' && is_next(&chars, ':') {
+            chars.pop_front();
+            tokens.push(TokenData { token: Token::PathSep, line: line_count });
+        }
         else if char == '=' {
             tokens.push(TokenData { token: Token::Equal, line: line_count });
         }
@@ -198,6 +417,24 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
         else if char == '*' {
             tokens.push(TokenData { token: Token::Multiply, line: line_count });
         }
+        else if char == ':' {
+            tokens.push(TokenData { token: Token::Colon, line: line_count });
+        }
+        else if char == ',' {
+            tokens.push(TokenData { token: Token::Comma, line: line_count });
+        }
+        else if char == '.' {
+            tokens.push(TokenData { token: Token::Dot, line: line_count });
+        }
+        else if char == '#' {
+            tokens.push(TokenData { token: Token::Hash, line: line_count });
+        }
+        else if char == '[' {
+            tokens.push(TokenData { token: Token::OpenSquare, line: line_count });
+        }
+        else if char == ']' {
+            tokens.push(TokenData { token: Token::CloseSquare, line: line_count });
+        }
         else if char == '{' {
             tokens.push(TokenData { token: Token::OpenScope, line: line_count });
         }
@@ -217,6 +454,29 @@ pub fn tokenize(content: &str) -> Vec<TokenData>{
     tokens
 }
 
+// The character after a `\`: `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"` or `\u{1F600}`
+fn escape(chars: &mut VecDeque<char>, line_count: i32) -> char {
+    match chars.pop_front() {
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('0') => '\0',
+        Some(element @ ('\\' | '\'' | '"')) => element,
+        Some('u') if is_next(chars, '{') => {
+            chars.pop_front();
+            let mut digits = String::new();
+            while let Some(element) = chars.pop_front().filter(|element| *element != '}') {
+                digits.push(element);
+            }
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or_else(|| panic!("Invalid unicode escape \\u{{{}}} at line {}", digits, line_count))
+        }
+        element => panic!("Invalid escape \\{} at line {}", element.unwrap_or(' '), line_count),
+    }
+}
+
 fn peek(chars: &VecDeque<char>, identifier: char, offset: usize) -> bool {
     if let Some(ele) = chars.get(offset)  {
         return *ele == identifier;
@@ -233,21 +493,25 @@ fn is_next(chars: &VecDeque<char>, identifier: char) -> bool {
 }
 
 
+// Loosest first: `||`, `&&`, comparisons, `+ -`, `* /`
 pub fn binary_precendence(token: &Token) -> i8 {
     match token {
-        Token::Add => 1, 
-        Token::Subtract => 1, 
-        Token::Multiply => 2, 
-        Token::Division => 2, 
-        Token::Equality => 2, 
-        Token::OR => 1, 
-        Token::AND => 1, 
-        Token::GreaterThan => 1,
-        Token::LessThan => 1,
-        Token::GreaterThanEqual => 1,
-        Token::LessThanEqual => 1,
+        Token::Add => 4, 
+        Token::Subtract => 4, 
+        Token::Multiply => 5, 
+        Token::Division => 5, 
+        Token::Equality => 3, 
+        Token::Or => 1, 
+        Token::And => 2, 
+        Token::GreaterThan => 3,
+        Token::LessThan => 3,
+        Token::GreaterThanEqual => 3,
+        Token::LessThanEqual => 3,
         Token::Exit => unreachable!(),
-        Token::IntLiteral(_) => unreachable!(),
+        Token::IntLiteral(..) => unreachable!(),
+        Token::FloatLiteral(_) => unreachable!(),
+        Token::CharLiteral(_) => unreachable!(),
+        Token::StringLiteral(_) => unreachable!(),
         Token::BooleanLiteral(_) => unreachable!(),
         Token::SemiColon => unreachable!(),
         Token::Let => unreachable!(),
@@ -263,5 +527,23 @@ pub fn binary_precendence(token: &Token) -> i8 {
         Token::ReturnSig => unreachable!(),
         Token::FuncSig => unreachable!(),
         Token::LitType(_) => unreachable!(),
+        Token::Hash => unreachable!(),
+        Token::OpenSquare => unreachable!(),
+        Token::CloseSquare => unreachable!(),
+        Token::Colon => unreachable!(),
+        Token::Comma => unreachable!(),
+        Token::As => unreachable!(),
+        Token::Print(_) => unreachable!(),
+        Token::Read(_) => unreachable!(),
+        Token::Struct => unreachable!(),
+        Token::Dot => unreachable!(),
+        Token::Enum => unreachable!(),
+        Token::Match => unreachable!(),
+        Token::FatArrow => unreachable!(),
+        Token::PathSep => unreachable!(),
+        Token::Underscore => unreachable!(),
+        Token::Const => unreachable!(),
+        Token::Static => unreachable!(),
+        Token::Mut => unreachable!(),
     }
 }
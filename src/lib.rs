@@ -3,7 +3,7 @@ use std::process::Command;
 use std::{collections::VecDeque, io::Read};
 
 use anyhow::{Context, Ok, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use tracing_subscriber::layer::SubscriberExt;
 
 use tracing::debug;
@@ -15,6 +15,7 @@ mod lexical;
 mod parsing;
 mod parse_validation;
 mod codegen;
+mod ir;
 
 
 #[derive(Debug, Parser)]
@@ -23,15 +24,85 @@ struct Args {
     file_name: String
 }
 
-pub fn main(file_name: String) -> Result<String> {
+/// What the compiler produces.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// Assemble, link and run the program
+    #[default]
+    Asm,
+    /// Print the intermediate representation
+    Ir,
+}
+
+/// How much work goes into the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OptLevel {
+    /// No optimizations, every value lives on the stack
+    #[value(name = "0")]
+    O0,
+    /// Constant folding, dead code elimination, tail calls, register allocation and peephole
+    #[value(name = "1")]
+    O1,
+    /// Everything in -O1 plus inlining
+    #[default]
+    #[value(name = "2")]
+    O2,
+}
+
+/// Compiler switches shared by every stage after parsing.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Emit overflow and division-by-zero traps around arithmetic and bounds
+    /// checks around array indexing.
+    pub runtime_checks: bool,
+    pub emit: Emit,
+    pub opt_level: OptLevel,
+    /// Report on stderr what every pass changed.
+    pub print_passes: bool,
+}
+
+type IrPass = (&'static str, fn(&mut ir::IrModule, &Options));
+
+/// IR passes run at `level`, in order.
+fn ir_passes(level: OptLevel) -> Vec<IrPass> {
+    let mut passes: Vec<IrPass> = vec![];
+    if level >= OptLevel::O1 {
+        passes.push(("constant-folding", |module, _| ir::fold_constants(module)));
+    }
+    if level >= OptLevel::O2 {
+        passes.push(("inlining", |module, _| ir::inline_calls(module)));
+        // Arguments that were constants now meet the callee's arithmetic
+        passes.push(("constant-folding", |module, _| ir::fold_constants(module)));
+    }
+    if level >= OptLevel::O1 {
+        passes.push(("dead-code", |module, options| {
+            ir::eliminate_dead_code(module, options.runtime_checks)
+        }));
+        passes.push(("tail-calls", |module, _| ir::mark_tail_calls(module)));
+    }
+    passes
+}
+
+fn report_pass(name: &str, summary: &str) {
+    eprintln!("{:<24} {}", name, summary);
+}
+
+// Functions, blocks and instructions of the module
+fn module_size(module: &ir::IrModule) -> [usize; 3] {
+    let blocks = module.funcs.iter().flat_map(|func| func.blocks.iter());
+    let insts = blocks.clone().map(|block| block.insts.len()).sum();
+    [module.funcs.len(), blocks.count(), insts]
+}
+
+pub fn main(file_name: String, options: Options) -> Result<String> {
     println!("{}", std::env::current_dir().unwrap().display());
 
     let layer = tracing_subscriber::fmt::layer().pretty();
 
-    tracing_subscriber::registry()
+    let _ = tracing_subscriber::registry()
         .with(layer)
         .with(EnvFilter::from_default_env())
-        .init();
+        .try_init();
 
     let mut file = std::fs::File::open(file_name)
         .context("Failed to read the File")?;
@@ -42,8 +113,32 @@ pub fn main(file_name: String) -> Result<String> {
     let mut content = String::new();
     file.read_to_string(&mut content).context("Failed to read file content")?;
 
+    let output = compile(&content, &options);
+    if options.emit == Emit::Ir {
+        print!("{}", output);
+        return Ok(output);
+    }
+    std::fs::write("out.s", output).context("Failed to write out.s")?;
+
+    let command = Command::new("sh")
+        .arg("-c")
+        .arg("as -arch arm64 out.s -o out.o && ld -o out out.o -lSystem -syslibroot `xcrun -sdk macosx --show-sdk-path` -e _start -arch arm64 && ./out; echo $?")
+        .output()
+        .expect("Command Failed");
+
+    let s = command.stdout;
+    let s = String::from_utf8(s).unwrap().trim().to_owned();
+
+    print!("Result {}", s);
+
+    Ok(s)
+}
+
+/// Runs every compiler stage over `content` and returns the AArch64 assembly,
+/// or the IR dump with [`Emit::Ir`].
+pub fn compile(content: &str, options: &Options) -> String {
     info!("Lexical Analysis being performed...");
-    let tokens = lexical::tokenize(&content);
+    let tokens = lexical::tokenize(content);
 
     for token in tokens.iter()  {
         debug!("{:?}", token);
@@ -53,30 +148,46 @@ pub fn main(file_name: String) -> Result<String> {
     info!("Parsing being performed...");
 
     let tokens = VecDeque::from(tokens);
-    let nodes = parsing::parse(tokens);
+    let mut nodes = parsing::parse(tokens);
 
     info!("Parsing completed.\n");
 
-    parse_validation::parse_validation(&nodes);
+    parse_validation::parse_validation(&mut nodes);
 
     for node in nodes.funcs.iter() {
         debug!("Node {:#?}\n", node);
     }
 
-    info!("Code Generation being performed...");
-    codegen::generate(nodes);
-    info!("Code Generation Completed");
-
-    let command = Command::new("sh")
-        .arg("-c")
-        .arg("as -arch arm64 out.s -o out.o && ld -o out out.o -lSystem -syslibroot `xcrun -sdk macosx --show-sdk-path` -e _start -arch arm64 && ./out; echo $?")
-        .output()
-        .expect("Command Failed");
+    info!("Lowering to IR...");
+    let mut module = ir::lower(&nodes);
+    info!("Lowering Completed");
+
+    for (name, pass) in ir_passes(options.opt_level) {
+        if !options.print_passes {
+            pass(&mut module, options);
+            continue;
+        }
+        let (before, size_before) = (module.to_string(), module_size(&module));
+        pass(&mut module, options);
+        let size = module_size(&module);
+        let summary = if module.to_string() == before {
+            "no changes".to_owned()
+        } else {
+            format!(
+                "functions {} -> {}, blocks {} -> {}, instructions {} -> {}",
+                size_before[0], size[0], size_before[1], size[1], size_before[2], size[2]
+            )
+        };
+        report_pass(name, &summary);
+    }
 
-    let s = command.stdout;
-    let s = String::from_utf8(s).unwrap().trim().to_owned();
+    if options.emit == Emit::Ir {
+        return module.to_string();
+    }
 
-    print!("Result {}", s);
+    info!("Code Generation being performed...");
+    let assembly = codegen::generate(&module, options);
+    info!("Code Generation Completed");
 
-    Ok(s)
+    assembly
 }
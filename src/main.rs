@@ -1,16 +1,38 @@
-
 use anyhow::{Ok, Result};
 use clap::Parser;
+use hydrogen::{Emit, OptLevel};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    file_name: String
+    file_name: String,
+
+    /// Trap on integer overflow, division by zero and out of bounds indices at runtime
+    #[arg(long)]
+    runtime_checks: bool,
+
+    /// Output to produce
+    #[arg(long, value_enum, default_value_t = Emit::Asm)]
+    emit: Emit,
+
+    /// Optimization level
+    #[arg(short = 'O', value_enum, default_value_t = OptLevel::O2)]
+    opt_level: OptLevel,
+
+    /// Report what every optimization pass changed
+    #[arg(long)]
+    print_passes: bool,
 }
 
 fn main() -> Result<()> {
     let arg = Args::parse();
     let file_name = arg.file_name;
-    let _ = hydrogen::main(file_name);
+    let options = hydrogen::Options {
+        runtime_checks: arg.runtime_checks,
+        emit: arg.emit,
+        opt_level: arg.opt_level,
+        print_passes: arg.print_passes,
+    };
+    let _ = hydrogen::main(file_name, options);
     Ok(())
 }
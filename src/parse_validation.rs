@@ -1,12 +1,930 @@
 use core::panic;
+use std::collections::{HashMap, HashSet};
 
-use crate::parsing::NodeRoot;
+use crate::{
+    cast,
+    lexical::{LitKind, Token},
+    parsing::{
+        NodeArray, NodeBiExpr, NodeBiOp, NodeElse, NodeEnum, NodeExpr, NodeFunc, NodeGlobal, NodeMatch, NodePattern,
+        NodeRoot, NodeScope, NodeStmt, NodeStruct, NodeTermExpr, Storage,
+    },
+};
 
- pub fn parse_validation(root: &NodeRoot) {
+/// Checks that `main` exists, that structs and enums have a size, that every
+/// `match` covers all values, that constants and statics are known at compile
+/// time and that every expression is well typed. Integer literals without a
+/// suffix get their type from the context here, lowering relies on every
+/// literal having one afterwards.
+ pub fn parse_validation(root: &mut NodeRoot) {
      let funcs = &root.funcs;
      let is_main_present = funcs.iter().any(|f| f.f_name == "main");
      if !is_main_present {
          tracing::error!("Main function is missing");
          panic!("Invalid Parsing");
      }
+     // It exits the process with its result, there is nothing to hold a struct
+     if funcs.iter().any(|f| f.f_name == "main" && matches!(f.return_type, Some(LitKind::Struct(_)))) {
+         panic!("main cannot return a struct");
+     }
+     if funcs.iter().any(|f| f.f_name == "main" && matches!(f.return_type, Some(LitKind::Enum(_)))) {
+         panic!("main cannot return an enum");
+     }
+     validate_structs(&root.structs);
+     validate_enums(&root.enums);
+     validate_sizes(root);
+
+     let signatures: HashMap<String, Signature> =
+         funcs.iter().map(|func| (func.f_name.clone(), Signature::new(func))).collect();
+     let globals = validate_globals(&mut root.globals, &signatures, &root.structs, &root.enums);
+     for func in root.funcs.iter_mut() {
+         Validator::new(func, &signatures, &globals, &root.structs, &root.enums).validate_func(func);
+     }
  }
+
+// Evaluates the value of every constant and static, which may use the constants declared before them
+fn validate_globals(
+    nodes: &mut [NodeGlobal],
+    signatures: &HashMap<String, Signature>,
+    structs: &[NodeStruct],
+    enums: &[NodeEnum],
+) -> HashMap<String, Global> {
+    let mut globals = HashMap::new();
+    for node in nodes.iter_mut() {
+        let name = cast!(&node.ident.token, Token::Indent).to_owned();
+        let item = match node.storage {
+            Storage::Const => format!("const {}", name),
+            Storage::Static { .. } => format!("static {}", name),
+        };
+        let mut validator = Validator {
+            signatures,
+            globals: &globals,
+            structs,
+            enums,
+            item,
+            return_type: None,
+            scopes: vec![],
+        };
+        if !(node.kind.is_integer() || matches!(node.kind, LitKind::Bool | LitKind::Char)) {
+            validator.error(format!("Type {} is not supported outside of functions", validator.type_name(node.kind)));
+        }
+        let found = validator.validate_expr(&mut node.expr, Some(node.kind));
+        validator.expect_type(node.kind, found);
+        let Some((value, _)) = validator.const_eval(&node.expr) else {
+            validator.error("Value is not known at compile time".to_owned());
+        };
+        node.value = Some(value);
+        let global = match node.storage {
+            Storage::Const => Global::Const(value, node.kind),
+            Storage::Static { mutable } => Global::Static { kind: node.kind, mutable },
+        };
+        if globals.insert(name.clone(), global).is_some() {
+            panic!("{} at line {} is defined more than once", name, node.ident.line);
+        }
+    }
+    globals
+}
+
+// Field names are unique
+fn validate_structs(structs: &[NodeStruct]) {
+    for node_struct in structs.iter() {
+        let mut names = HashSet::new();
+        for field in node_struct.fields.iter() {
+            let name = cast!(&field.ident.token, Token::Indent);
+            if !names.insert(name) {
+                panic!("Field {} of struct {} is declared more than once", name, node_struct.name);
+            }
+        }
+    }
+}
+
+// Variant names are unique
+fn validate_enums(enums: &[NodeEnum]) {
+    for node_enum in enums.iter() {
+        let mut names = HashSet::new();
+        for variant in node_enum.variants.iter() {
+            if !names.insert(&variant.name) {
+                panic!("Variant {} of enum {} is declared more than once", variant.name, node_enum.name);
+            }
+        }
+    }
+}
+
+// No struct or enum contains itself, which would make it infinitely large
+fn validate_sizes(root: &NodeRoot) {
+    let inner = |kind: LitKind| -> Vec<LitKind> {
+        match kind {
+            LitKind::Struct(id) => root.structs[id].fields.iter().map(|field| field.kind).collect(),
+            LitKind::Enum(id) => root.enums[id].variants.iter().flat_map(|variant| variant.fields.clone()).collect(),
+            _ => vec![],
+        }
+    };
+    let structs = root.structs.iter().enumerate().map(|(id, node)| (LitKind::Struct(id), "Struct", &node.name, node.line));
+    let enums = root.enums.iter().enumerate().map(|(id, node)| (LitKind::Enum(id), "Enum", &node.name, node.line));
+    for (kind, what, name, line) in structs.chain(enums) {
+        let mut pending = vec![kind];
+        let mut seen = HashSet::new();
+        while let Some(current) = pending.pop() {
+            for field in inner(current) {
+                if field == kind {
+                    panic!("{} {} contains itself at line {}", what, name, line);
+                }
+                if seen.insert(field) {
+                    pending.push(field);
+                }
+            }
+        }
+    }
+}
+
+struct Signature {
+    params: Vec<LitKind>,
+    /// `None` for a function without a return type
+    return_type: Option<LitKind>,
+}
+
+impl Signature {
+    fn new(func: &NodeFunc) -> Signature {
+        Signature {
+            params: func.params.iter().map(|param| param.kind).collect(),
+            return_type: func.return_type,
+        }
+    }
+}
+
+fn fits(kind: &LitKind, value: i128) -> bool {
+    let (min, max) = kind.range();
+    (min..=max).contains(&value)
+}
+
+// `value` truncated to the width of `kind`, the way a cast does
+fn wrap(kind: &LitKind, value: i128) -> i128 {
+    let (min, max) = kind.range();
+    (value - min).rem_euclid(max - min + 1) + min
+}
+
+// Made of integer literals without a suffix only, which take the type of the other operand
+fn is_untyped(expr: &NodeExpr) -> bool {
+    match expr {
+        NodeExpr::Term(NodeTermExpr::IntLiteral(_, None)) => true,
+        NodeExpr::Term(NodeTermExpr::Expression(expr)) => is_untyped(expr),
+        NodeExpr::BinaryExpr(binary_expr) => {
+            is_arithmetic(&binary_expr.op) && is_untyped(&binary_expr.lhs) && is_untyped(&binary_expr.rhs)
+        }
+        _ => false,
+    }
+}
+
+// Integers are accepted as conditions, non zero is true
+fn is_condition(kind: &LitKind) -> bool {
+    *kind == LitKind::Bool || kind.is_integer()
+}
+
+// Control can reach the end of the statements, none of them returns on every path
+fn can_complete(stmts: &[NodeStmt]) -> bool {
+    !stmts.iter().any(always_returns)
+}
+
+fn always_returns(stmt: &NodeStmt) -> bool {
+    match stmt {
+        NodeStmt::Return { .. } => true,
+        NodeStmt::Scope { scope } => !can_complete(&scope.0),
+        NodeStmt::If { scope, chain, .. } => !can_complete(&scope.0) && else_returns(chain),
+        // Every value is covered by some arm
+        NodeStmt::Match(node) => !node.arms.is_empty() && node.arms.iter().all(|arm| !can_complete(&arm.body.0)),
+        _ => false,
+    }
+}
+
+// Without an `else` the condition can be false and skip every branch
+fn else_returns(node_else: &Option<NodeElse>) -> bool {
+    match node_else {
+        Some(NodeElse::ElseIf { scope, chain, .. }) => !can_complete(&scope.0) && else_returns(chain),
+        Some(NodeElse::Else(scope)) => !can_complete(&scope.0),
+        None => false,
+    }
+}
+
+fn is_arithmetic(op: &NodeBiOp) -> bool {
+    matches!(op, NodeBiOp::Add | NodeBiOp::Subtract | NodeBiOp::Multiply | NodeBiOp::Division)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Global {
+    /// Value and type, known at compile time
+    Const(i128, LitKind),
+    Static { kind: LitKind, mutable: bool },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    Scalar(LitKind),
+    /// Element type and length
+    Array(LitKind, usize),
+}
+
+struct Validator<'a> {
+    signatures: &'a HashMap<String, Signature>,
+    // Constants and statics, locals shadow them
+    globals: &'a HashMap<String, Global>,
+    structs: &'a [NodeStruct],
+    enums: &'a [NodeEnum],
+    // What errors are reported in, like `function main`
+    item: String,
+    return_type: Option<LitKind>,
+    // Variables in scope, innermost scope last. Unknown names are left to
+    // lowering, which reports them.
+    scopes: Vec<HashMap<String, Binding>>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(
+        func: &NodeFunc,
+        signatures: &'a HashMap<String, Signature>,
+        globals: &'a HashMap<String, Global>,
+        structs: &'a [NodeStruct],
+        enums: &'a [NodeEnum],
+    ) -> Validator<'a> {
+        let params = func
+            .params
+            .iter()
+            .map(|param| (cast!(&param.ident.token, Token::Indent).to_owned(), Binding::Scalar(param.kind)))
+            .collect();
+        Validator {
+            signatures,
+            globals,
+            structs,
+            enums,
+            item: format!("function {}", func.f_name),
+            return_type: func.return_type,
+            scopes: vec![params],
+        }
+    }
+
+    fn validate_func(&mut self, func: &mut NodeFunc) {
+        self.validate_stmts(&mut func.stmts);
+        if self.return_type.is_some() && can_complete(&func.stmts) {
+            self.error("Missing return".to_owned());
+        }
+    }
+
+    fn error(&self, message: String) -> ! {
+        tracing::error!("{}", message);
+        panic!("{} in {}", message, self.item);
+    }
+
+    fn type_name(&self, kind: LitKind) -> &str {
+        match kind {
+            LitKind::Struct(id) => &self.structs[id].name,
+            LitKind::Enum(id) => &self.enums[id].name,
+            kind => kind.name(),
+        }
+    }
+
+    // Type of `field` in a value of type `kind`
+    fn field_kind(&self, kind: LitKind, field: &str) -> LitKind {
+        let LitKind::Struct(id) = kind else {
+            self.error(format!("{} has no field {}", self.type_name(kind), field));
+        };
+        let node_struct = &self.structs[id];
+        node_struct
+            .fields
+            .iter()
+            .find(|candidate| cast!(&candidate.ident.token, Token::Indent) == field)
+            .map(|candidate| candidate.kind)
+            .unwrap_or_else(|| self.error(format!("Struct {} has no field {}", node_struct.name, field)))
+    }
+
+    fn binding(&self, identifier: &str) -> Option<Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(identifier).copied())
+    }
+
+    // A constant or static the name refers to, when no local is declared with it
+    fn global(&self, identifier: &str) -> Option<Global> {
+        match self.binding(identifier) {
+            Some(_) => None,
+            None => self.globals.get(identifier).copied(),
+        }
+    }
+
+    fn lookup(&self, identifier: &str) -> Option<LitKind> {
+        if let Some(Global::Const(_, kind) | Global::Static { kind, .. }) = self.global(identifier) {
+            return Some(kind);
+        }
+        match self.binding(identifier)? {
+            Binding::Scalar(kind) => Some(kind),
+            Binding::Array(..) => self.error(format!("{} is an array and can only be indexed", identifier)),
+        }
+    }
+
+    fn lookup_array(&self, identifier: &str) -> Option<(LitKind, usize)> {
+        if self.global(identifier).is_some() {
+            self.error(format!("{} is not an array", identifier));
+        }
+        match self.binding(identifier)? {
+            Binding::Array(kind, len) => Some((kind, len)),
+            Binding::Scalar(_) => self.error(format!("{} is not an array", identifier)),
+        }
+    }
+
+    fn declare(&mut self, identifier: &str, binding: Binding) {
+        self.scopes.last_mut().unwrap().insert(identifier.to_owned(), binding);
+    }
+
+    fn expect_element(&self, element: Option<LitKind>, found: Option<LitKind>) {
+        if let Some(element) = element {
+            self.expect_type(element, found);
+        }
+    }
+
+    fn expect_type(&self, expected: LitKind, found: Option<LitKind>) {
+        if let Some(found) = found {
+            if found != expected {
+                self.error(format!("Mismatched types: expected {} but found {}", self.type_name(expected), self.type_name(found)));
+            }
+        }
+    }
+}
+
+// Statement
+
+impl Validator<'_> {
+    fn validate_stmts(&mut self, stmts: &mut [NodeStmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts.iter_mut() {
+            self.validate_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn validate_scope(&mut self, scope: &mut NodeScope) {
+        self.validate_stmts(&mut scope.0);
+    }
+
+    fn validate_stmt(&mut self, stmt: &mut NodeStmt) {
+        match stmt {
+            NodeStmt::Let { ident, kind, expr } => {
+                let found = self.validate_expr(expr, *kind);
+                if let Some(kind) = kind {
+                    self.expect_type(*kind, found);
+                }
+                if let Some(found) = kind.or(found) {
+                    *kind = Some(found);
+                    self.declare(cast!(&ident.token, Token::Indent), Binding::Scalar(found));
+                }
+            }
+            NodeStmt::LetArray { ident, kind, init } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                let mut element = kind.map(|(kind, _)| kind);
+                match init {
+                    NodeArray::Repeat { expr, .. } => {
+                        let found = self.validate_expr(expr, element);
+                        element = element.or(found);
+                        self.expect_element(element, found);
+                    }
+                    // Without a type the first element decides it
+                    NodeArray::List(exprs) => {
+                        for expr in exprs.iter_mut() {
+                            let found = self.validate_expr(expr, element);
+                            element = element.or(found);
+                            self.expect_element(element, found);
+                        }
+                    }
+                }
+                if let Some((_, len)) = kind {
+                    if *len != init.len() {
+                        self.error(format!("Array {} has length {} but {} elements were given", identifier, len, init.len()));
+                    }
+                }
+                if matches!(init, NodeArray::List(exprs) if exprs.is_empty()) && element.is_none() {
+                    self.error(format!("Type of the empty array {} is missing", identifier));
+                }
+                if let Some(LitKind::Struct(id)) = element {
+                    self.error(format!("Array {} cannot hold the struct {}", identifier, self.structs[id].name));
+                }
+                if let Some(LitKind::Enum(id)) = element.filter(|_| !self.is_c_like(element)) {
+                    self.error(format!("Array {} cannot hold the enum {}", identifier, self.enums[id].name));
+                }
+                if let Some(element) = element {
+                    *kind = Some((element, init.len()));
+                    self.declare(identifier, Binding::Array(element, init.len()));
+                }
+            }
+            NodeStmt::AssignIndex { ident, index, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                let array = self.lookup_array(identifier);
+                self.check_index(index);
+                self.check_bounds(identifier, index);
+                let found = self.validate_expr(expr, array.map(|(kind, _)| kind));
+                if let Some((kind, _)) = array {
+                    self.expect_type(kind, found);
+                }
+            }
+            NodeStmt::AssignField { ident, fields, expr } => {
+                let target = self
+                    .lookup(cast!(&ident.token, Token::Indent))
+                    .map(|kind| fields.iter().fold(kind, |kind, field| self.field_kind(kind, field)));
+                let found = self.validate_expr(expr, target);
+                if let Some(target) = target {
+                    self.expect_type(target, found);
+                }
+            }
+            NodeStmt::ReAssign { ident, expr } => {
+                let identifier = cast!(&ident.token, Token::Indent);
+                match self.global(identifier) {
+                    Some(Global::Const(..)) => self.error(format!("Cannot assign to the constant {}", identifier)),
+                    Some(Global::Static { mutable: false, .. }) => {
+                        self.error(format!("Cannot assign to {}, the static is not mut", identifier))
+                    }
+                    _ => {}
+                }
+                let target = self.lookup(identifier);
+                let found = self.validate_expr(expr, target);
+                if let Some(target) = target {
+                    self.expect_type(target, found);
+                }
+            }
+            NodeStmt::Return { expr } => match (expr, self.return_type) {
+                (Some(expr), Some(return_type)) => {
+                    let found = self.validate_expr(expr, Some(return_type));
+                    self.expect_type(return_type, found);
+                }
+                (Some(_), None) => self.error("Cannot return a value from a function without a return type".to_owned()),
+                (None, Some(return_type)) => {
+                    self.error(format!("Missing return value, expected {}", self.type_name(return_type)))
+                }
+                (None, None) => {}
+            },
+            NodeStmt::Scope { scope } => {
+                self.validate_scope(scope);
+            }
+            NodeStmt::Print { expr, .. } => {
+                let printable = |kind: &LitKind| kind.is_integer() || matches!(kind, LitKind::Bool | LitKind::Str);
+                if let Some(found) = self.validate_expr(expr, None).filter(|kind| !printable(kind)) {
+                    self.error(format!("Cannot print {}", self.type_name(found)));
+                }
+            }
+            NodeStmt::If { expr, scope, chain } => {
+                self.validate_condition(expr);
+                self.validate_scope(scope);
+                self.validate_else(chain);
+            }
+            NodeStmt::Match(node) => {
+                self.check_match(node, |validator, scope| validator.validate_scope(scope));
+            }
+            // A call is the only expression that may give no value
+            NodeStmt::Expr { expr } => match expr {
+                NodeExpr::Call { name, args } => {
+                    self.check_call(name, args);
+                    self.const_eval(expr);
+                }
+                _ => {
+                    self.validate_expr(expr, None);
+                }
+            },
+        }
+    }
+
+    fn validate_else(&mut self, node_else: &mut Option<NodeElse>) {
+        match node_else {
+            Some(NodeElse::ElseIf { expr, scope, chain }) => {
+                self.validate_condition(expr);
+                self.validate_scope(scope);
+                self.validate_else(chain);
+            }
+            Some(NodeElse::Else(scope)) => self.validate_scope(scope),
+            None => {}
+        }
+    }
+
+    fn validate_condition(&mut self, expr: &mut NodeExpr) {
+        if let Some(found) = self.validate_expr(expr, Some(LitKind::Bool)).filter(|kind| !is_condition(kind)) {
+            self.error(format!("Mismatched types: expected bool but found {}", self.type_name(found)));
+        }
+    }
+
+    // Types `expr`, then diagnoses its constant parts
+    fn validate_expr(&mut self, expr: &mut NodeExpr, expected: Option<LitKind>) -> Option<LitKind> {
+        let kind = self.check_expr(expr, expected);
+        self.const_eval(expr);
+        kind
+    }
+}
+
+// Types
+
+impl Validator<'_> {
+    /// Type of `expr`, `None` when it uses something undefined. An untyped
+    /// literal becomes `expected` when that is an integer type, i32 otherwise.
+    fn check_expr(&mut self, expr: &mut NodeExpr, expected: Option<LitKind>) -> Option<LitKind> {
+        match expr {
+            NodeExpr::Term(term) => self.check_term(term, expected),
+            NodeExpr::Call { name, args } => match self.check_call(name, args)? {
+                Some(kind) => Some(kind),
+                None => self.error(format!("Function {} does not return a value", name)),
+            },
+            NodeExpr::BinaryExpr(binary_expr) => self.check_binary(binary_expr, expected),
+            NodeExpr::Read(kind) => Some(*kind),
+            NodeExpr::Index { name, index } => {
+                let array = self.lookup_array(name);
+                self.check_index(index);
+                array.map(|(kind, _)| kind)
+            }
+            NodeExpr::StructLiteral { id, fields } => {
+                let structs = self.structs;
+                let node_struct = &structs[*id];
+                let mut given = HashSet::new();
+                for (ident, expr) in fields.iter_mut() {
+                    let name = cast!(&ident.token, Token::Indent);
+                    let kind = self.field_kind(LitKind::Struct(*id), name);
+                    if !given.insert(name.to_owned()) {
+                        self.error(format!("Field {} of {} is given more than once", name, node_struct.name));
+                    }
+                    let found = self.check_expr(expr, Some(kind));
+                    self.expect_type(kind, found);
+                }
+                for field in node_struct.fields.iter() {
+                    let name = cast!(&field.ident.token, Token::Indent);
+                    if !given.contains(name) {
+                        self.error(format!("Field {} of {} is missing", name, node_struct.name));
+                    }
+                }
+                Some(LitKind::Struct(*id))
+            }
+            NodeExpr::Field { expr, field } => {
+                let kind = self.check_expr(expr, None)?;
+                Some(self.field_kind(kind, field))
+            }
+            NodeExpr::Variant { id, variant, args } => {
+                let enums = self.enums;
+                let node_enum = &enums[*id];
+                let Some(index) = node_enum.variant(variant) else {
+                    self.error(format!("Enum {} has no variant {}", node_enum.name, variant));
+                };
+                let fields = &node_enum.variants[index].fields;
+                if fields.len() != args.len() {
+                    self.error(format!(
+                        "Variant {}::{} takes {} values but {} were given",
+                        node_enum.name,
+                        variant,
+                        fields.len(),
+                        args.len()
+                    ));
+                }
+                for (arg, field) in args.iter_mut().zip(fields) {
+                    let found = self.check_expr(arg, Some(*field));
+                    self.expect_type(*field, found);
+                }
+                Some(LitKind::Enum(*id))
+            }
+            NodeExpr::Match { node, kind } => {
+                let expected = expected.filter(|_| kind.is_none());
+                let mut result = *kind;
+                self.check_match(node, |validator, body| {
+                    let found = validator.validate_expr(body, result.or(expected));
+                    result = result.or(found);
+                    validator.expect_element(result, found);
+                });
+                if node.arms.is_empty() {
+                    self.error(format!("Match at line {} has no arm to take its value from", node.line));
+                }
+                *kind = result;
+                result
+            }
+            NodeExpr::Block(block) => {
+                self.scopes.push(HashMap::new());
+                for stmt in block.stmts.iter_mut() {
+                    self.validate_stmt(stmt);
+                }
+                let found = self.validate_expr(&mut block.expr, expected);
+                self.scopes.pop();
+                found
+            }
+            NodeExpr::If { cond, then, otherwise, line, kind } => {
+                self.validate_condition(cond);
+                let expected = kind.or(expected);
+                let then_kind = self.check_expr(then, expected);
+                let else_kind = self.check_expr(otherwise, then_kind.or(expected));
+                if let (Some(then_kind), Some(else_kind)) = (then_kind, else_kind) {
+                    if then_kind != else_kind {
+                        self.error(format!(
+                            "Branches of the if at line {} give {} and {}",
+                            line,
+                            self.type_name(then_kind),
+                            self.type_name(else_kind)
+                        ));
+                    }
+                }
+                *kind = then_kind.or(else_kind);
+                *kind
+            }
+            NodeExpr::Cast { expr, kind } => {
+                // Numbers convert into each other, bool and char into integers
+                // and u8 into char. Nothing becomes a bool and str stays str.
+                // An enum without values converts into integers, its tag.
+                let found = self.check_expr(expr, None);
+                let invalid = |found: &LitKind| match (*found, *kind) {
+                    (found, kind) if found == kind => false,
+                    (LitKind::Enum(_), kind) => !(self.is_c_like(Some(*found)) && kind.is_integer()),
+                    (_, LitKind::Bool | LitKind::Str) | (LitKind::Str | LitKind::Struct(_), _) => true,
+                    (found, LitKind::Char) => found != LitKind::U8,
+                    (LitKind::Bool | LitKind::Char, kind) => !kind.is_integer(),
+                    _ => false,
+                };
+                if let Some(found) = found.filter(invalid) {
+                    self.error(format!("Cannot cast {} to {}", self.type_name(found), kind.name()));
+                }
+                Some(*kind)
+            }
+        }
+    }
+
+    // Types the arguments against the parameters. The return type of the
+    // function, `None` when it is not defined.
+    fn check_call(&mut self, name: &str, args: &mut [NodeExpr]) -> Option<Option<LitKind>> {
+        let signatures = self.signatures;
+        let Some(signature) = signatures.get(name) else {
+            for arg in args.iter_mut() {
+                self.check_expr(arg, None);
+            }
+            return None;
+        };
+        // Argument counts are checked while lowering
+        for (index, arg) in args.iter_mut().enumerate() {
+            let param = signature.params.get(index).copied();
+            let found = self.check_expr(arg, param);
+            if let Some(param) = param {
+                self.expect_type(param, found);
+            }
+        }
+        Some(signature.return_type)
+    }
+
+    fn check_binary(&mut self, binary_expr: &mut NodeBiExpr, expected: Option<LitKind>) -> Option<LitKind> {
+        let NodeBiExpr { lhs, rhs, op } = binary_expr;
+        if matches!(op, NodeBiOp::Or | NodeBiOp::And) {
+            for operand in [lhs, rhs] {
+                if let Some(found) = self.check_expr(operand, Some(LitKind::Bool)).filter(|kind| !is_condition(kind)) {
+                    self.error(format!("Operator {:?} is not defined for {}", op, self.type_name(found)));
+                }
+            }
+            return Some(LitKind::Bool);
+        }
+
+        // Both operands have the same type, an untyped side takes the other one's
+        let hint = expected.filter(|kind| is_arithmetic(op) && kind.is_integer());
+        let (lhs_kind, rhs_kind) = if is_untyped(lhs) {
+            let rhs_kind = self.check_expr(rhs, hint);
+            (self.check_expr(lhs, rhs_kind.or(hint)), rhs_kind)
+        } else {
+            let lhs_kind = self.check_expr(lhs, hint);
+            (lhs_kind, self.check_expr(rhs, lhs_kind.or(hint)))
+        };
+
+        if let (Some(lhs_kind), Some(rhs_kind)) = (lhs_kind, rhs_kind) {
+            if lhs_kind != rhs_kind {
+                self.error(format!("Mismatched types: {} {:?} {}", self.type_name(lhs_kind), op, self.type_name(rhs_kind)));
+            }
+            let defined = match lhs_kind {
+                LitKind::Bool => matches!(op, NodeBiOp::Equality),
+                LitKind::Char => !is_arithmetic(op),
+                LitKind::Str | LitKind::Struct(_) | LitKind::Enum(_) => false,
+                _ => true,
+            };
+            if !defined {
+                self.error(format!("Operator {:?} is not defined for {}", op, self.type_name(lhs_kind)));
+            }
+        }
+        match is_arithmetic(op) {
+            true => lhs_kind.or(rhs_kind),
+            false => Some(LitKind::Bool),
+        }
+    }
+
+    // An enum none of whose variants carries a value
+    fn is_c_like(&self, kind: Option<LitKind>) -> bool {
+        matches!(kind, Some(LitKind::Enum(id)) if self.enums[id].is_c_like())
+    }
+
+    /// Types the patterns against the matched value and every arm with its
+    /// bindings in scope. Rejects arms that can never be taken and values no
+    /// arm covers, only enum variants and bools can be covered without `_`.
+    fn check_match<T>(&mut self, node: &mut NodeMatch<T>, mut check_body: impl FnMut(&mut Self, &mut T)) {
+        let found = self.validate_expr(&mut node.expr, None);
+        let matchable = |kind: &LitKind| kind.is_integer() || matches!(kind, LitKind::Bool | LitKind::Char | LitKind::Enum(_));
+        if let Some(found) = found.filter(|kind| !matchable(kind)) {
+            self.error(format!("Cannot match on {} at line {}", self.type_name(found), node.line));
+        }
+
+        let mut covered = HashSet::new();
+        let mut wildcard = false;
+        for arm in node.arms.iter_mut() {
+            if wildcard {
+                self.error(format!("Unreachable pattern at line {}", arm.line));
+            }
+            let mut bindings = HashMap::new();
+            let value = match &mut arm.pattern {
+                NodePattern::Wildcard => {
+                    wildcard = true;
+                    None
+                }
+                NodePattern::Literal(term) => {
+                    let kind = self.check_term(term, found);
+                    if matches!(found, Some(LitKind::Enum(_))) {
+                        self.error(format!("Expected a variant of {} at line {}", self.type_name(found.unwrap()), arm.line));
+                    }
+                    if let Some(kind) = kind {
+                        self.expect_type(found.unwrap_or(kind), Some(kind));
+                    }
+                    self.const_eval_term(term).map(|(value, _)| value)
+                }
+                NodePattern::Variant { id, variant, bindings: names } => {
+                    let node_enum = &self.enums[*id];
+                    self.expect_type(found.unwrap_or(LitKind::Enum(*id)), Some(LitKind::Enum(*id)));
+                    let Some(index) = node_enum.variant(variant) else {
+                        self.error(format!("Enum {} has no variant {}", node_enum.name, variant));
+                    };
+                    let fields = &node_enum.variants[index].fields;
+                    if fields.len() != names.len() {
+                        self.error(format!(
+                            "Variant {}::{} holds {} values but the pattern at line {} names {}",
+                            node_enum.name,
+                            variant,
+                            fields.len(),
+                            arm.line,
+                            names.len()
+                        ));
+                    }
+                    for (name, field) in names.iter().zip(fields) {
+                        if let Some(name) = name {
+                            bindings.insert(cast!(&name.token, Token::Indent).to_owned(), Binding::Scalar(*field));
+                        }
+                    }
+                    Some(index as i128)
+                }
+            };
+            if value.is_some_and(|value| !covered.insert(value)) {
+                self.error(format!("Unreachable pattern at line {}", arm.line));
+            }
+
+            self.scopes.push(bindings);
+            check_body(self, &mut arm.body);
+            self.scopes.pop();
+        }
+
+        let missing = match found {
+            _ if wildcard => None,
+            Some(LitKind::Enum(id)) => self.enums[id]
+                .variants
+                .iter()
+                .enumerate()
+                .find(|(index, _)| !covered.contains(&(*index as i128)))
+                .map(|(_, variant)| format!("{}::{}", self.enums[id].name, variant.name)),
+            Some(LitKind::Bool) => [false, true]
+                .into_iter()
+                .find(|value| !covered.contains(&(*value as i128)))
+                .map(|value| value.to_string()),
+            Some(_) => Some("`_`".to_owned()),
+            None => None,
+        };
+        if let Some(missing) = missing {
+            self.error(format!("Match at line {} does not cover {}", node.line, missing));
+        }
+    }
+
+    fn check_term(&mut self, term: &mut NodeTermExpr, expected: Option<LitKind>) -> Option<LitKind> {
+        match term {
+            NodeTermExpr::IntLiteral(literal, kind) => {
+                let resolved = kind.or(expected.filter(LitKind::is_integer)).unwrap_or(LitKind::I32);
+                *kind = Some(resolved);
+                if !literal.parse::<i128>().is_ok_and(|value| fits(&resolved, value)) {
+                    tracing::error!("Literal {} does not fit in {}", literal, resolved.name());
+                    panic!(
+                        "Integer literal {} does not fit in {} in {}",
+                        literal, resolved.name(), self.item
+                    );
+                }
+                Some(resolved)
+            }
+            NodeTermExpr::FloatLiteral(_) => Some(LitKind::F64),
+            NodeTermExpr::CharLiteral(_) => Some(LitKind::Char),
+            NodeTermExpr::StringLiteral(_) => Some(LitKind::Str),
+            NodeTermExpr::BooleanLiteral(_) => Some(LitKind::Bool),
+            NodeTermExpr::Identifier(identifier) => self.lookup(identifier),
+            NodeTermExpr::Expression(expr) => self.check_expr(expr, expected),
+        }
+    }
+
+    // Any integer type indexes, untyped literals become i32
+    fn check_index(&mut self, index: &mut NodeExpr) {
+        if let Some(found) = self.check_expr(index, None).filter(|kind| !kind.is_integer()) {
+            self.error(format!("Array index has to be an integer but found {}", self.type_name(found)));
+        }
+    }
+}
+
+// Constants
+
+impl Validator<'_> {
+    /// Rejects constant indices outside of the array, others are checked at
+    /// runtime with runtime checks.
+    fn check_bounds(&self, name: &str, index: &NodeExpr) {
+        let (Some((value, _)), Some((_, len))) = (self.const_eval(index), self.lookup_array(name)) else {
+            return;
+        };
+        if value < 0 || value >= len as i128 {
+            self.error(format!("Index {} is out of bounds for {} of length {}", value, name, len));
+        }
+    }
+
+    /// Evaluates `expr` when it is built only from literals, diagnosing constant
+    /// division by zero and results outside the range of their type. Returns
+    /// `None` for floats and anything that depends on runtime values.
+    fn const_eval(&self, expr: &NodeExpr) -> Option<(i128, LitKind)> {
+        match expr {
+            NodeExpr::Term(term) => self.const_eval_term(term),
+            NodeExpr::Call { args, .. } => {
+                for arg in args.iter() {
+                    self.const_eval(arg);
+                }
+                None
+            }
+            NodeExpr::Read(_) => None,
+            NodeExpr::Index { name, index } => {
+                self.check_bounds(name, index);
+                None
+            }
+            NodeExpr::StructLiteral { fields, .. } => {
+                for (_, expr) in fields.iter() {
+                    self.const_eval(expr);
+                }
+                None
+            }
+            NodeExpr::Field { expr, .. } => {
+                self.const_eval(expr);
+                None
+            }
+            NodeExpr::Variant { args, .. } => {
+                for arg in args.iter() {
+                    self.const_eval(arg);
+                }
+                None
+            }
+            // The arms and blocks were diagnosed while typing, with their bindings in scope
+            NodeExpr::Match { .. } | NodeExpr::Block(_) | NodeExpr::If { .. } => None,
+            NodeExpr::Cast { expr, kind } => {
+                let (value, _) = self.const_eval(expr)?;
+                kind.is_integer().then(|| (wrap(kind, value), *kind))
+            }
+            NodeExpr::BinaryExpr(binary_expr) => {
+                let lhs = self.const_eval(&binary_expr.lhs);
+                let rhs = self.const_eval(&binary_expr.rhs);
+
+                if let (NodeBiOp::Division, Some((0, _))) = (&binary_expr.op, rhs) {
+                    tracing::error!("Division by zero in {}", self.item);
+                    panic!("Division by zero in {}", self.item);
+                }
+
+                let ((lhs, kind), (rhs, _)) = (lhs?, rhs?);
+                let value = match binary_expr.op {
+                    NodeBiOp::Add => lhs.checked_add(rhs),
+                    NodeBiOp::Subtract => lhs.checked_sub(rhs),
+                    NodeBiOp::Multiply => lhs.checked_mul(rhs),
+                    NodeBiOp::Division => lhs.checked_div(rhs),
+                    NodeBiOp::Equality => return Some(((lhs == rhs) as i128, LitKind::Bool)),
+                    NodeBiOp::GreaterThan => return Some(((lhs > rhs) as i128, LitKind::Bool)),
+                    NodeBiOp::LessThan => return Some(((lhs < rhs) as i128, LitKind::Bool)),
+                    NodeBiOp::GreaterThanEqual => return Some(((lhs >= rhs) as i128, LitKind::Bool)),
+                    NodeBiOp::LessThanEqual => return Some(((lhs <= rhs) as i128, LitKind::Bool)),
+                    NodeBiOp::Or => return Some(((lhs != 0 || rhs != 0) as i128, LitKind::Bool)),
+                    NodeBiOp::And => return Some(((lhs != 0 && rhs != 0) as i128, LitKind::Bool)),
+                };
+
+                match value.filter(|value| fits(&kind, *value)) {
+                    Some(value) => Some((value, kind)),
+                    None => {
+                        tracing::error!("Constant expression overflows {} in {}", kind.name(), self.item);
+                        panic!("Arithmetic overflow in constant expression in {}", self.item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn const_eval_term(&self, term: &NodeTermExpr) -> Option<(i128, LitKind)> {
+        match term {
+            NodeTermExpr::IntLiteral(literal, kind) => {
+                let value = literal.parse().expect("Literal is checked while typing");
+                Some((value, kind.expect("Literal is typed before it is evaluated")))
+            }
+            NodeTermExpr::BooleanLiteral(value) => Some((*value as i128, LitKind::Bool)),
+            // Floats cannot overflow or trap and strings do not compute, nothing to diagnose
+            NodeTermExpr::FloatLiteral(_) | NodeTermExpr::StringLiteral(_) => None,
+            NodeTermExpr::CharLiteral(value) => Some((*value as i128, LitKind::Char)),
+            NodeTermExpr::Identifier(identifier) => match self.global(identifier)? {
+                Global::Const(value, kind) => Some((value, kind)),
+                Global::Static { .. } => None,
+            },
+            NodeTermExpr::Expression(expr) => self.const_eval(expr),
+        }
+    }
+}
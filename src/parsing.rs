@@ -1,5 +1,5 @@
 use core::panic;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use tracing::debug;
 
@@ -9,30 +9,105 @@ use crate::{cast, lexical::{binary_precendence, LitKind, Token, TokenData}};
 #[derive(Debug)]
 pub struct NodeRoot {
    pub funcs: Vec<NodeFunc>,
+   /// `LitKind::Struct` indexes into these
+   pub structs: Vec<NodeStruct>,
+   /// `LitKind::Enum` indexes into these
+   pub enums: Vec<NodeEnum>,
+   pub globals: Vec<NodeGlobal>,
+}
+
+/// `const N: i32 = 10;` or `static mut COUNTER: i32 = 0;` outside of any function.
+#[derive(Debug)]
+pub struct NodeGlobal {
+    pub ident: TokenData,
+    pub kind: LitKind,
+    pub expr: NodeExpr,
+    pub storage: Storage,
+    /// Value of `expr`, filled in by validation
+    pub value: Option<i128>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// Replaced by its value wherever it is used
+    Const,
+    /// Lives in the data section, `mutable` when declared `static mut`
+    Static { mutable: bool },
+}
+
+/// `struct Point { x: i32, y: i32 }`, the fields are written like parameters.
+#[derive(Debug)]
+pub struct NodeStruct {
+    pub name: String,
+    pub line: i32,
+    pub fields: Vec<NodeParam>,
+}
+
+/// `enum Shape { Circle(i32), Rect(i32, i32), Empty }`, a variant may carry values.
+#[derive(Debug)]
+pub struct NodeEnum {
+    pub name: String,
+    pub line: i32,
+    pub variants: Vec<NodeVariant>,
+}
+
+#[derive(Debug)]
+pub struct NodeVariant {
+    pub name: String,
+    pub fields: Vec<LitKind>,
+}
+
+impl NodeEnum {
+    /// No variant carries a value, so the enum is only its tag
+    pub fn is_c_like(&self) -> bool {
+        self.variants.iter().all(|variant| variant.fields.is_empty())
+    }
+
+    pub fn variant(&self, name: &str) -> Option<usize> {
+        self.variants.iter().position(|variant| variant.name == name)
+    }
 }
 
 #[derive(Debug)]
 pub struct NodeFunc {
     pub f_name: String,
+    pub params: Vec<NodeParam>,
     pub stmts: Vec<NodeStmt>,
+    /// `None` without `-> T`, the function gives no value
     pub return_type: Option<LitKind>,
+    pub inline: Option<InlineHint>,
 }
 
 impl NodeFunc {
 
-    fn new(name: String, stmts: Vec<NodeStmt>, ret_type: Option<LitKind>) -> NodeFunc {
-        NodeFunc { f_name: name, stmts, return_type: ret_type }
+    fn new(name: String, params: Vec<NodeParam>, stmts: Vec<NodeStmt>, ret_type: Option<LitKind>) -> NodeFunc {
+        NodeFunc { f_name: name, params, stmts, return_type: ret_type, inline: None }
     }
     
 }
 
+#[derive(Debug)]
+pub struct NodeParam {
+    pub ident: TokenData,
+    pub kind: LitKind,
+}
+
+/// `#[inline]` or `#[inline(never)]` in front of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineHint {
+    Always,
+    Never,
+}
+
 #[derive(Debug)]
 pub struct NodeScope(pub Vec<NodeStmt>);
 
 #[derive(Debug)]
 pub enum NodeStmt {
-    Let{  ident: TokenData, expr: NodeExpr },
-    Return { expr:NodeExpr },
+    /// The type is filled in by validation when missing
+    Let{  ident: TokenData, kind: Option<LitKind>, expr: NodeExpr },
+    /// `return;` in a function without a return type
+    Return { expr: Option<NodeExpr> },
     Scope { scope: NodeScope },
     If {
         expr: NodeExpr,
@@ -40,6 +115,60 @@ pub enum NodeStmt {
         chain: Option<NodeElse>
     },
     ReAssign{ expr: NodeExpr, ident: TokenData},
+    /// `let a: [i32; 3] = [1, 2, 3];`, the type is filled in by validation when missing
+    LetArray { ident: TokenData, kind: Option<(LitKind, usize)>, init: NodeArray },
+    /// `a[index] = expr;`
+    AssignIndex { ident: TokenData, index: NodeExpr, expr: NodeExpr },
+    /// `a.b.c = expr;` with the field names in order
+    AssignField { ident: TokenData, fields: Vec<String>, expr: NodeExpr },
+    /// `print(expr);`, `println(expr);` adds a new line
+    Print { expr: NodeExpr, newline: bool },
+    /// `match expr { pattern => { ... } }`
+    Match(NodeMatch<NodeScope>),
+    /// `expr;` like a call, the value is dropped
+    Expr { expr: NodeExpr },
+}
+
+/// `match expr { ... }`, the first arm whose pattern fits is taken.
+#[derive(Debug)]
+pub struct NodeMatch<T> {
+    pub expr: NodeExpr,
+    pub line: i32,
+    pub arms: Vec<NodeArm<T>>,
+}
+
+#[derive(Debug)]
+pub struct NodeArm<T> {
+    pub pattern: NodePattern,
+    pub line: i32,
+    pub body: T,
+}
+
+#[derive(Debug)]
+pub enum NodePattern {
+    /// `_`
+    Wildcard,
+    /// An integer, char or bool literal
+    Literal(NodeTermExpr),
+    /// `Shape::Rect(w, _)` binds the values the variant carries, `_` skips one
+    Variant { id: usize, variant: String, bindings: Vec<Option<TokenData>> },
+}
+
+#[derive(Debug)]
+pub enum NodeArray {
+    /// `[expr; len]`
+    Repeat { expr: NodeExpr, len: usize },
+    /// `[a, b, c]`
+    List(Vec<NodeExpr>),
+}
+
+impl NodeArray {
+    pub fn len(&self) -> usize {
+        match self {
+            NodeArray::Repeat { len, .. } => *len,
+            NodeArray::List(exprs) => exprs.len(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -57,12 +186,42 @@ pub enum NodeElse {
 pub enum NodeExpr {
    BinaryExpr(Box<NodeBiExpr>),
    Term(NodeTermExpr),
-   Call(String),
+   Call { name: String, args: Vec<NodeExpr> },
+   /// `expr as u8`
+   Cast { expr: Box<NodeExpr>, kind: LitKind },
+   /// `read_int()` as i64 or `read_line()` as str
+   Read(LitKind),
+   /// `name[index]`
+   Index { name: String, index: Box<NodeExpr> },
+   /// `Point { x: 1, y: 2 }`, `id` indexes `NodeRoot::structs`
+   StructLiteral { id: usize, fields: Vec<(TokenData, NodeExpr)> },
+   /// `expr.field`
+   Field { expr: Box<NodeExpr>, field: String },
+   /// `Color::Red` or `Shape::Rect(2, 3)`, `id` indexes `NodeRoot::enums`
+   Variant { id: usize, variant: String, args: Vec<NodeExpr> },
+   /// `match expr { pattern => expr, ... }`, the type is filled in by validation
+   Match { node: Box<NodeMatch<NodeExpr>>, kind: Option<LitKind> },
+   /// `{ stmts expr }`
+   Block(Box<NodeBlock>),
+   /// `if (cond) { ... } else { ... }`, `then` is a block and `otherwise` a block or
+   /// another `if`. The type is filled in by validation.
+   If { cond: Box<NodeExpr>, then: Box<NodeExpr>, otherwise: Box<NodeExpr>, line: i32, kind: Option<LitKind> },
+}
+
+/// Statements followed by the expression that gives the block its value
+#[derive(Debug)]
+pub struct NodeBlock {
+    pub stmts: Vec<NodeStmt>,
+    pub expr: NodeExpr,
 }
 
 #[derive(Debug)]
 pub enum NodeTermExpr {
-   IntLiteral(String),
+   /// Digits and the type, from the suffix or the context after validation
+   IntLiteral(String, Option<LitKind>),
+   FloatLiteral(String),
+   CharLiteral(char),
+   StringLiteral(String),
    BooleanLiteral(bool),
    Identifier(String),
    Expression(Box<NodeExpr>),
@@ -78,12 +237,15 @@ pub struct NodeBiExpr {
 
 #[derive(Debug)]
 pub enum NodeBiOp {
-    Add, Multiply, Subtract, Division, Equality, OR, AND,
+    Add, Multiply, Subtract, Division, Equality, Or, And,
     GreaterThan, LessThan, LessThanEqual, GreaterThanEqual,
 }
 
 struct Parser {
     tokens: VecDeque<TokenData>,
+    // Ids of the declared structs and enums, known before any function is parsed
+    structs: HashMap<String, usize>,
+    enums: HashMap<String, usize>,
 } 
 
 
@@ -116,6 +278,27 @@ impl Parser {
         panic!("Missing {:?} ", token, );
     }
 
+    // A built in type or the name of a struct or enum
+    fn parse_type(&mut self, line: i32) -> LitKind {
+        let found = self.tokens.pop_front().map(|found| found.token);
+        match &found {
+            Some(Token::LitType(kind)) => *kind,
+            Some(Token::Indent(name)) => match (self.structs.get(name), self.enums.get(name)) {
+                (Some(id), _) => LitKind::Struct(*id),
+                (_, Some(id)) => LitKind::Enum(*id),
+                _ => panic!("Unknown type {} at line {}", name, line),
+            },
+            _ => panic!("Expected a type at line {} but found {:?}", line, found),
+        }
+    }
+
+    fn expect_ident(&mut self, line: i32) -> TokenData {
+        match self.tokens.pop_front() {
+            Some(found) if matches!(found.token, Token::Indent(_)) => found,
+            found => panic!("Expected a name at line {} but found {:?}", line, found.map(|found| found.token)),
+        }
+    }
+
     fn expect_expr(&mut self) -> NodeExpr {
         if let Some(expr) = self.parse_expr(1) {
             return expr;
@@ -130,33 +313,73 @@ impl Parser {
 impl Parser {
 
     fn parse_func(&mut self) -> Option<NodeFunc> {
-        let name_token = self.tokens.pop_front()?.token;
-        let fuc_name = cast!(&name_token, Token::Indent);
+        let name_token = self.tokens.pop_front()?;
+        let line = name_token.line;
+        let fuc_name = cast!(&name_token.token, Token::Indent);
         self.expect(Token::OpenBracket);
-        self.expect(Token::CloseBracket);
-        self.expect(Token::ReturnSig);
-        let ret_type = self.tokens.pop_front().map(|f| {
-            cast!(f.token, Token::LitType)
-        });
+        let params = self.parse_params();
+        let mut ret_type = None;
+        if self.peek_expect(0, Token::ReturnSig) {
+            self.expect(Token::ReturnSig);
+            ret_type = Some(self.parse_type(line));
+        }
         self.expect(Token::OpenScope);
         let stmts = self.parse_stmts();
-        Some(NodeFunc::new(fuc_name.to_owned(), stmts, ret_type))
+        Some(NodeFunc::new(fuc_name.to_owned(), params, stmts, ret_type))
     }
-    
-    fn parse_expr(&mut self, min_prec: i8) -> Option<NodeExpr> {
-        if let Some(token) = self.peek(0) {
-            if let Token::Indent(ident) = &token.token  {
-                if self.peek_expect(1, Token::OpenBracket)
-                    && self.peek_expect(2, Token::CloseBracket) {
-                        let ident = ident.to_owned();
-                        self.consume_count(3);
-                        return Some(NodeExpr::Call(ident));
-                    }
+
+    // `a: i32, b: bool)`, the '(' is already consumed
+    fn parse_params(&mut self) -> Vec<NodeParam> {
+        let mut params = vec![];
+        while !self.peek_expect(0, Token::CloseBracket) {
+            let ident = self.tokens.pop_front().expect("parameter missing");
+            if !matches!(ident.token, Token::Indent(_)) {
+                panic!("Expected a parameter name at line {} but found {:?}", ident.line, ident.token);
+            }
+            self.expect(Token::Colon);
+            let kind = self.parse_type(ident.line);
+            params.push(NodeParam { ident, kind });
+            if !self.peek_expect(0, Token::CloseBracket) {
+                self.expect(Token::Comma);
             }
         }
+        self.expect(Token::CloseBracket);
+        params
+    }
 
-        let term_option = self.parse_term()?;
-        let mut lhs = NodeExpr::Term(term_option);
+    // `a + 1, b)`, the '(' is already consumed
+    fn parse_args(&mut self) -> Vec<NodeExpr> {
+        let mut args = vec![];
+        while !self.peek_expect(0, Token::CloseBracket) {
+            args.push(self.expect_expr());
+            if !self.peek_expect(0, Token::CloseBracket) {
+                self.expect(Token::Comma);
+            }
+        }
+        self.expect(Token::CloseBracket);
+        args
+    }
+    
+    fn parse_expr(&mut self, min_prec: i8) -> Option<NodeExpr> {
+        let lhs = self
+            .parse_call()
+            .or_else(|| self.parse_index())
+            .or_else(|| self.parse_struct_literal())
+            .or_else(|| self.parse_variant())
+            .or_else(|| self.parse_match_expr())
+            .or_else(|| self.parse_if_expr())
+            .or_else(|| self.parse_block_expr())
+            .or_else(|| self.parse_term().map(NodeExpr::Term))?;
+        let mut lhs = self.parse_fields(lhs);
+        // `as` binds tighter than any binary operator
+        while self.peek_expect(0, Token::As) {
+            let line = self.tokens.pop_front().unwrap().line;
+            let kind = self.tokens.pop_front().map(|found| found.token);
+            let Some(Token::LitType(kind)) = kind else {
+                panic!("Expected a type after `as` at line {} but found {:?}", line, kind);
+            };
+            lhs = NodeExpr::Cast { expr: Box::new(lhs), kind };
+        }
 
         while let Some(next) = self.tokens.front()  {
             if !is_binary_operator(&next.token) || binary_precendence(&next.token) < min_prec  {
@@ -174,17 +397,317 @@ impl Parser {
     }
 
 
+    fn parse_call(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        if let Token::Read(kind) = token.token {
+            self.consume_count(1);
+            self.expect(Token::OpenBracket);
+            self.expect(Token::CloseBracket);
+            return Some(NodeExpr::Read(kind));
+        }
+        if let Token::Indent(ident) = &token.token  {
+            if self.peek_expect(1, Token::OpenBracket) {
+                let name = ident.to_owned();
+                self.consume_count(2);
+                let args = self.parse_args();
+                return Some(NodeExpr::Call { name, args });
+            }
+        }
+        None
+    }
+
+    fn parse_index(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        if let Token::Indent(ident) = &token.token {
+            if self.peek_expect(1, Token::OpenSquare) {
+                let name = ident.to_owned();
+                self.consume_count(2);
+                let index = self.expect_expr();
+                self.expect(Token::CloseSquare);
+                return Some(NodeExpr::Index { name, index: Box::new(index) });
+            }
+        }
+        None
+    }
+
+    // `Point { x: 1, y: 2 }`
+    fn parse_struct_literal(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        let Token::Indent(name) = &token.token else { return None };
+        let id = *self.structs.get(name)?;
+        if !self.peek_expect(1, Token::OpenScope) {
+            return None;
+        }
+        let line = token.line;
+        self.consume_count(2);
+        let mut fields = vec![];
+        while !self.peek_expect(0, Token::CloseScope) {
+            let field = self.expect_ident(line);
+            self.expect(Token::Colon);
+            fields.push((field, self.expect_expr()));
+            if !self.peek_expect(0, Token::CloseScope) {
+                self.expect(Token::Comma);
+            }
+        }
+        self.expect(Token::CloseScope);
+        Some(NodeExpr::StructLiteral { id, fields })
+    }
+
+    // `Color::Red` or `Shape::Rect(2, 3)`
+    fn parse_variant(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        let Token::Indent(name) = &token.token else { return None };
+        let id = *self.enums.get(name)?;
+        let line = token.line;
+        self.consume_count(1);
+        self.expect(Token::PathSep);
+        let variant = cast!(self.expect_ident(line).token, Token::Indent);
+        let mut args = vec![];
+        if self.peek_expect(0, Token::OpenBracket) {
+            self.expect(Token::OpenBracket);
+            args = self.parse_args();
+        }
+        Some(NodeExpr::Variant { id, variant, args })
+    }
+
+    // `match expr { pattern => expr, ... }`
+    fn parse_match_expr(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        if token.token != Token::Match {
+            return None;
+        }
+        let line = token.line;
+        self.consume_count(1);
+        let node = self.parse_match(line, |parser| parser.expect_expr());
+        Some(NodeExpr::Match { node: Box::new(node), kind: None })
+    }
+
+    // `if (cond) { ... } else { ... }`
+    fn parse_if_expr(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        if token.token != Token::If {
+            return None;
+        }
+        let line = token.line;
+        self.consume_count(1);
+        Some(self.parse_if_value(line))
+    }
+
+    // `(cond) { ... } else { ... }`, the `if` or `else if` is already consumed
+    fn parse_if_value(&mut self, line: i32) -> NodeExpr {
+        self.expect(Token::OpenBracket);
+        let cond = self.expect_expr();
+        self.expect(Token::CloseBracket);
+        self.expect(Token::OpenScope);
+        let then = NodeExpr::Block(Box::new(self.parse_block(line)));
+        let otherwise = match self.tokens.pop_front() {
+            Some(found) if found.token == Token::ElseIf => self.parse_if_value(found.line),
+            Some(found) if found.token == Token::Else => {
+                self.expect(Token::OpenScope);
+                NodeExpr::Block(Box::new(self.parse_block(found.line)))
+            }
+            _ => panic!("If at line {} needs an else to give a value", line),
+        };
+        NodeExpr::If { cond: Box::new(cond), then: Box::new(then), otherwise: Box::new(otherwise), line, kind: None }
+    }
+
+    fn parse_block_expr(&mut self) -> Option<NodeExpr> {
+        let token = self.peek(0)?;
+        if token.token != Token::OpenScope {
+            return None;
+        }
+        let line = token.line;
+        self.consume_count(1);
+        Some(NodeExpr::Block(Box::new(self.parse_block(line))))
+    }
+
+    // `stmts expr }`, the `{` is already consumed
+    fn parse_block(&mut self, line: i32) -> NodeBlock {
+        let mut stmts = vec![];
+        loop {
+            let Some(token) = self.peek(0) else {
+                panic!("Block at line {} is not closed", line);
+            };
+            if token.token == Token::CloseScope {
+                panic!("Block at line {} has no value", line);
+            }
+            let is_stmt = matches!(token.token, Token::Let | Token::Exit | Token::Print(_)) || !self.ends_block();
+            if !is_stmt {
+                let expr = self.expect_expr();
+                self.expect(Token::CloseScope);
+                return NodeBlock { stmts, expr };
+            }
+            let token = self.tokens.pop_front().unwrap();
+            stmts.extend(self.parse_stmt(token));
+        }
+    }
+
+    // Whether the next item runs up to the `}` closing its block, which makes it the
+    // value of the block. An `if`, `match` or `{ ... }` ends with its last `}`,
+    // anything else with a `;`.
+    fn ends_block(&self) -> bool {
+        let braced = matches!(self.peek(0).map(|found| &found.token), Some(Token::If | Token::Match | Token::OpenScope));
+        let mut depth = 0;
+        for (index, found) in self.tokens.iter().enumerate() {
+            match found.token {
+                Token::OpenBracket | Token::OpenSquare | Token::OpenScope => depth += 1,
+                Token::CloseBracket | Token::CloseSquare => depth -= 1,
+                Token::CloseScope if depth == 0 => return true,
+                Token::CloseScope => {
+                    depth -= 1;
+                    let next = self.peek(index + 1).map(|found| &found.token);
+                    if braced && depth == 0 && !matches!(next, Some(Token::Else | Token::ElseIf)) {
+                        return next == Some(&Token::CloseScope);
+                    }
+                }
+                Token::SemiColon if depth == 0 => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // `expr { pattern => body, ... }`, the `match` is already consumed. A comma
+    // separates the arms, it is optional after a `{ ... }` body.
+    fn parse_match<T>(&mut self, line: i32, parse_body: impl Fn(&mut Parser) -> T) -> NodeMatch<T> {
+        let expr = self.expect_expr();
+        self.expect(Token::OpenScope);
+        let mut arms = vec![];
+        while !self.peek_expect(0, Token::CloseScope) {
+            let line = self.peek(0).map_or(line, |token| token.line);
+            let pattern = self.parse_pattern(line);
+            self.expect(Token::FatArrow);
+            let braced = self.peek_expect(0, Token::OpenScope);
+            let body = parse_body(self);
+            arms.push(NodeArm { pattern, line, body });
+            if self.peek_expect(0, Token::Comma) || !(braced || self.peek_expect(0, Token::CloseScope)) {
+                self.expect(Token::Comma);
+            }
+        }
+        self.expect(Token::CloseScope);
+        NodeMatch { expr, line, arms }
+    }
+
+    fn parse_pattern(&mut self, line: i32) -> NodePattern {
+        let token = self.tokens.pop_front().map(|found| found.token);
+        match token {
+            Some(Token::Underscore) => NodePattern::Wildcard,
+            Some(Token::IntLiteral(digits, kind)) => NodePattern::Literal(NodeTermExpr::IntLiteral(digits, kind)),
+            Some(Token::Subtract) => match self.tokens.pop_front().map(|found| found.token) {
+                Some(Token::IntLiteral(digits, kind)) => {
+                    NodePattern::Literal(NodeTermExpr::IntLiteral(format!("-{}", digits), kind))
+                }
+                found => panic!("Expected an integer after `-` at line {} but found {:?}", line, found),
+            },
+            Some(Token::CharLiteral(value)) => NodePattern::Literal(NodeTermExpr::CharLiteral(value)),
+            Some(Token::BooleanLiteral(value)) => NodePattern::Literal(NodeTermExpr::BooleanLiteral(value)),
+            Some(Token::Indent(name)) if self.enums.contains_key(&name) => {
+                let id = self.enums[&name];
+                self.expect(Token::PathSep);
+                let variant = cast!(self.expect_ident(line).token, Token::Indent);
+                let mut bindings = vec![];
+                if self.peek_expect(0, Token::OpenBracket) {
+                    self.expect(Token::OpenBracket);
+                    while !self.peek_expect(0, Token::CloseBracket) {
+                        match self.tokens.pop_front() {
+                            Some(found) if found.token == Token::Underscore => bindings.push(None),
+                            Some(found) if matches!(found.token, Token::Indent(_)) => bindings.push(Some(found)),
+                            found => panic!(
+                                "Expected a name or `_` at line {} but found {:?}",
+                                line,
+                                found.map(|found| found.token)
+                            ),
+                        }
+                        if !self.peek_expect(0, Token::CloseBracket) {
+                            self.expect(Token::Comma);
+                        }
+                    }
+                    self.expect(Token::CloseBracket);
+                }
+                NodePattern::Variant { id, variant, bindings }
+            }
+            found => panic!("Expected a pattern at line {} but found {:?}", line, found),
+        }
+    }
+
+    // `.x.y` after an expression
+    fn parse_fields(&mut self, mut expr: NodeExpr) -> NodeExpr {
+        while self.peek_expect(0, Token::Dot) {
+            let line = self.tokens.pop_front().unwrap().line;
+            let field = self.expect_ident(line);
+            let field = cast!(field.token, Token::Indent);
+            expr = NodeExpr::Field { expr: Box::new(expr), field };
+        }
+        expr
+    }
+
+    // `i32; 10]`, the '[' is already consumed
+    fn parse_array_type(&mut self, line: i32) -> (LitKind, usize) {
+        let kind = self.tokens.pop_front().map(|found| found.token);
+        let Some(Token::LitType(kind)) = kind else {
+            panic!("Expected the element type of an array at line {} but found {:?}", line, kind);
+        };
+        self.expect(Token::SemiColon);
+        let len = self.parse_array_len(line);
+        self.expect(Token::CloseSquare);
+        (kind, len)
+    }
+
+    fn parse_array_len(&mut self, line: i32) -> usize {
+        let len = self.tokens.pop_front().map(|found| found.token);
+        match &len {
+            Some(Token::IntLiteral(digits, None)) => digits
+                .parse()
+                .unwrap_or_else(|_| panic!("Array length {} at line {} is too large", digits, line)),
+            _ => panic!("Expected an integer literal as array length at line {} but found {:?}", line, len),
+        }
+    }
+
+    // `[expr; len]` or `[a, b, c]`, the '[' is already consumed
+    fn parse_array(&mut self, line: i32) -> NodeArray {
+        let mut exprs = vec![];
+        while !self.peek_expect(0, Token::CloseSquare) {
+            exprs.push(self.expect_expr());
+            if exprs.len() == 1 && self.peek_expect(0, Token::SemiColon) {
+                self.expect(Token::SemiColon);
+                let len = self.parse_array_len(line);
+                self.expect(Token::CloseSquare);
+                return NodeArray::Repeat { expr: exprs.pop().unwrap(), len };
+            }
+            if !self.peek_expect(0, Token::CloseSquare) {
+                self.expect(Token::Comma);
+            }
+        }
+        self.expect(Token::CloseSquare);
+        NodeArray::List(exprs)
+    }
+
     fn parse_term(&mut self) -> Option<NodeTermExpr> {
         if let Some(element) = self.tokens.front() {
             if let Token::BooleanLiteral(token) = &element.token {
-                let token = token.clone();
+                let token = *token;
                 self.tokens.pop_front().unwrap();
                 return Some(NodeTermExpr::BooleanLiteral(token));
             }
-            if let Token::IntLiteral(token) = &element.token {
+            if let Token::IntLiteral(token, kind) = &element.token {
+                let (token, kind) = (token.to_string(), *kind);
+                self.tokens.pop_front().unwrap();
+                return Some(NodeTermExpr::IntLiteral(token, kind));
+            }
+            if let Token::FloatLiteral(token) = &element.token {
                 let token = token.to_string();
                 self.tokens.pop_front().unwrap();
-                return Some(NodeTermExpr::IntLiteral(token));
+                return Some(NodeTermExpr::FloatLiteral(token));
+            }
+            if let Token::CharLiteral(token) = &element.token {
+                let token = *token;
+                self.tokens.pop_front().unwrap();
+                return Some(NodeTermExpr::CharLiteral(token));
+            }
+            if let Token::StringLiteral(token) = &element.token {
+                let token = token.to_string();
+                self.tokens.pop_front().unwrap();
+                return Some(NodeTermExpr::StringLiteral(token));
             }
             if let Token::Indent(token) = &element.token {
                 let token = token.to_string();
@@ -207,129 +730,313 @@ impl Parser {
 impl Parser {
 
     fn parse_else(&mut self) -> Option<NodeElse> {
-        while let Some(token) = self.tokens.front() {
-            match token.token {
-                Token::ElseIf => {
-                    self.tokens.pop_front();
-                    self.expect(Token::OpenBracket);
-                    let expr = self.expect_expr();
-                    self.expect(Token::CloseBracket);
-                    self.expect(Token::OpenScope);
-                    let if_statments = self.parse_stmts();
-                    let scope = NodeScope(if_statments);
-                    let chain = Box::new(self.parse_else());
-
-                    return Some(NodeElse::ElseIf { expr, scope, chain })
-                },
-
+        let token = self.tokens.front()?;
+        match token.token {
+            Token::ElseIf => {
+                self.tokens.pop_front();
+                self.expect(Token::OpenBracket);
+                let expr = self.expect_expr();
+                self.expect(Token::CloseBracket);
+                self.expect(Token::OpenScope);
+                let if_statments = self.parse_stmts();
+                let scope = NodeScope(if_statments);
+                let chain = Box::new(self.parse_else());
 
-                Token::Else => {
-                    self.tokens.pop_front();
-                    self.expect(Token::OpenScope);
-                    let if_statments = self.parse_stmts();
-                    let scope = NodeScope(if_statments);
-                    return Some(NodeElse::Else(scope))
-                },
+                Some(NodeElse::ElseIf { expr, scope, chain })
+            },
 
-                _ => {
-                    return None;
-                },
+            Token::Else => {
+                self.tokens.pop_front();
+                self.expect(Token::OpenScope);
+                let if_statments = self.parse_stmts();
+                let scope = NodeScope(if_statments);
+                Some(NodeElse::Else(scope))
+            },
 
-            }
+            _ => None,
         }
-        None
     }
 
     fn parse_stmts(&mut self,) -> Vec<NodeStmt> {
         let mut stmts = vec![];
         while let Some(token) = self.tokens.pop_front() {
-            match token.token {
-                Token::Exit => {
-                    let expr = self.expect_expr();
-                    stmts.push(NodeStmt::Return { expr });
-                    self.expect(Token::SemiColon);
-                },
-                Token::Let => {
-                    let ident = self.tokens.pop_front().expect("identifier missing");
-                    self.expect(Token::Equal);
-                    let expr = self.expect_expr();
+            if token.token == Token::CloseScope {
+                return stmts;
+            }
+            stmts.extend(self.parse_stmt(token));
+        }
+
+        stmts
+    }
+
+    // The statement starting with `token`, `None` for a nested function, which is skipped
+    fn parse_stmt(&mut self, token: TokenData) -> Option<NodeStmt> {
+        let stmt = match token.token {
+            Token::Exit => {
+                let mut expr = None;
+                if !self.peek_expect(0, Token::SemiColon) {
+                    expr = Some(self.expect_expr());
+                }
+                self.expect(Token::SemiColon);
+                NodeStmt::Return { expr }
+            },
+            Token::Let => {
+                let ident = self.tokens.pop_front().expect("identifier missing");
+                let mut kind = None;
+                let mut array_kind = None;
+                if self.peek_expect(0, Token::Colon) {
+                    self.expect(Token::Colon);
+                    if self.peek_expect(0, Token::OpenSquare) {
+                        self.expect(Token::OpenSquare);
+                        array_kind = Some(self.parse_array_type(ident.line));
+                    } else {
+                        kind = Some(self.parse_type(ident.line));
+                    }
+                }
+                self.expect(Token::Equal);
+                if array_kind.is_some() || self.peek_expect(0, Token::OpenSquare) {
+                    self.expect(Token::OpenSquare);
+                    let init = self.parse_array(ident.line);
                     self.expect(Token::SemiColon);
-                    stmts.push(NodeStmt::Let { expr , ident } );
-                },
-                Token::OpenScope => {
-                    let scoped_stmts = self.parse_stmts();
-                    stmts.push(NodeStmt::Scope { scope: NodeScope(scoped_stmts) });
+                    return Some(NodeStmt::LetArray { ident, kind: array_kind, init });
                 }
+                let expr = self.expect_expr();
+                self.expect(Token::SemiColon);
+                NodeStmt::Let { expr, ident, kind }
+            },
+            Token::OpenScope => {
+                let scoped_stmts = self.parse_stmts();
+                NodeStmt::Scope { scope: NodeScope(scoped_stmts) }
+            }
 
-                Token::CloseScope => {
-                    return stmts;
+            Token::If => {
+                self.expect(Token::OpenBracket);
+                let expr = self.expect_expr();
+                debug!("Parsing If condition {:?}", expr);
+                self.expect(Token::CloseBracket);
+                self.expect(Token::OpenScope);
+                let if_statments = self.parse_stmts();
+                let scope = NodeScope(if_statments);
+                let chain = self.parse_else();
+                NodeStmt::If { expr, scope, chain }
+            },
+
+            Token::Indent(_) if self.peek_expect(0, Token::Equal) => {
+                self.expect(Token::Equal);
+                let expr = self.expect_expr();
+                self.expect(Token::SemiColon);
+                NodeStmt::ReAssign { expr , ident: token }
+            },
+
+            Token::Indent(_) if self.peek_expect(0, Token::OpenSquare) => {
+                self.expect(Token::OpenSquare);
+                let index = self.expect_expr();
+                self.expect(Token::CloseSquare);
+                self.expect(Token::Equal);
+                let expr = self.expect_expr();
+                self.expect(Token::SemiColon);
+                NodeStmt::AssignIndex { ident: token, index, expr }
+            },
+
+            Token::Indent(_) if self.peek_expect(0, Token::Dot) => {
+                let mut fields = vec![];
+                while self.peek_expect(0, Token::Dot) {
+                    self.expect(Token::Dot);
+                    let field = self.expect_ident(token.line);
+                    fields.push(cast!(field.token, Token::Indent));
                 }
-                Token::If => {
-                    self.expect(Token::OpenBracket);
-                    let expr = self.expect_expr();
-                    debug!("Parsing If condition {:?}", expr);
-                    self.expect(Token::CloseBracket);
-                    self.expect(Token::OpenScope);
-                    let if_statments = self.parse_stmts();
-                    let scope = NodeScope(if_statments);
-                    let chain = self.parse_else();
-                    stmts.push(NodeStmt::If { expr, scope, chain });
-                },
-
-                Token::Indent(_) if self.peek_expect(0, Token::Equal) => {
-                    self.expect(Token::Equal);
-                    let expr = self.expect_expr();
-                    self.expect(Token::SemiColon);
-                    stmts.push(NodeStmt::ReAssign { expr , ident: token } );
-                },
+                self.expect(Token::Equal);
+                let expr = self.expect_expr();
+                self.expect(Token::SemiColon);
+                NodeStmt::AssignField { ident: token, fields, expr }
+            },
 
-                Token::FuncSig => {
-                    self.parse_func().expect("Function parsing failed");
-                },
+            Token::Match => {
+                let node = self.parse_match(token.line, |parser| {
+                    parser.expect(Token::OpenScope);
+                    NodeScope(parser.parse_stmts())
+                });
+                NodeStmt::Match(node)
+            },
 
-                _ => { continue; },
-            }
-        }
+            Token::Print(newline) => {
+                self.expect(Token::OpenBracket);
+                let expr = self.expect_expr();
+                self.expect(Token::CloseBracket);
+                self.expect(Token::SemiColon);
+                NodeStmt::Print { expr, newline }
+            },
 
-        stmts
+            _ => {
+                let line = token.line;
+                self.tokens.push_front(token);
+                let Some(expr) = self.parse_expr(1) else {
+                    let found = self.tokens.front().map(|found| &found.token);
+                    panic!("Expected a statement at line {} but found {:?}", line, found);
+                };
+                self.expect(Token::SemiColon);
+                NodeStmt::Expr { expr }
+            },
+        };
+        Some(stmt)
     }
 
-    fn parse_file(&mut self) -> Vec<NodeFunc> {
+    fn parse_file(&mut self) -> NodeRoot {
 
         let mut funcs = vec![];
+        let mut structs = vec![];
+        let mut enums = vec![];
+        let mut globals = vec![];
+        let mut inline = None;
+
+        // Structs and enums can be used before their declaration
+        let names = self.tokens.iter().zip(self.tokens.iter().skip(1)).filter_map(|(first, second)| {
+            match (&first.token, &second.token) {
+                (Token::Struct | Token::Enum, Token::Indent(name)) => Some((first.token == Token::Struct, name.to_owned())),
+                _ => None,
+            }
+        });
+        for (is_struct, name) in names.collect::<Vec<_>>() {
+            let types = if is_struct { &mut self.structs } else { &mut self.enums };
+            let id = types.len();
+            if types.insert(name.clone(), id).is_some() {
+                panic!("{} {} is defined more than once", if is_struct { "Struct" } else { "Enum" }, name);
+            }
+        }
+        if let Some(name) = self.structs.keys().find(|name| self.enums.contains_key(*name)) {
+            panic!("{} is defined as a struct and as an enum", name);
+        }
 
         while let Some(token) = self.tokens.pop_front() {
-            match token.token {
-                Token::FuncSig => {
-                    let func = self.parse_func().expect("Function parsing failed");
-                    funcs.push(func);
-                },
+            if token.token == Token::Hash {
+                inline = Some(self.parse_attribute(token.line));
+            }
+            if token.token == Token::FuncSig {
+                let mut func = self.parse_func().expect("Function parsing failed");
+                func.inline = inline.take();
+                funcs.push(func);
+            }
+            if token.token == Token::Struct {
+                structs.push(self.parse_struct(token.line));
+            }
+            if token.token == Token::Enum {
+                enums.push(self.parse_enum(token.line));
+            }
+            if token.token == Token::Const {
+                globals.push(self.parse_global(Storage::Const, token.line));
+            }
+            if token.token == Token::Static {
+                let mutable = self.peek_expect(0, Token::Mut);
+                if mutable {
+                    self.expect(Token::Mut);
+                }
+                globals.push(self.parse_global(Storage::Static { mutable }, token.line));
+            }
+        }
+
+        NodeRoot { funcs, structs, enums, globals }
+    }
+
+    // `N: i32 = 10;`, the `const` or `static mut` is already consumed
+    fn parse_global(&mut self, storage: Storage, line: i32) -> NodeGlobal {
+        let ident = self.expect_ident(line);
+        self.expect(Token::Colon);
+        let kind = self.parse_type(line);
+        self.expect(Token::Equal);
+        let expr = self.expect_expr();
+        self.expect(Token::SemiColon);
+        NodeGlobal { ident, kind, expr, storage, value: None }
+    }
+
+    // `Shape { Circle(i32), Empty }`, the `enum` is already consumed
+    fn parse_enum(&mut self, line: i32) -> NodeEnum {
+        let name = cast!(self.expect_ident(line).token, Token::Indent);
+        self.expect(Token::OpenScope);
+        let mut variants = vec![];
+        while !self.peek_expect(0, Token::CloseScope) {
+            let ident = self.expect_ident(line);
+            let mut fields = vec![];
+            if self.peek_expect(0, Token::OpenBracket) {
+                self.expect(Token::OpenBracket);
+                while !self.peek_expect(0, Token::CloseBracket) {
+                    fields.push(self.parse_type(ident.line));
+                    if !self.peek_expect(0, Token::CloseBracket) {
+                        self.expect(Token::Comma);
+                    }
+                }
+                self.expect(Token::CloseBracket);
+            }
+            variants.push(NodeVariant { name: cast!(ident.token, Token::Indent), fields });
+            if !self.peek_expect(0, Token::CloseScope) {
+                self.expect(Token::Comma);
+            }
+        }
+        self.expect(Token::CloseScope);
+        NodeEnum { name, line, variants }
+    }
 
-                _ => {},
+    // `Point { x: i32, y: i32 }`, the `struct` is already consumed
+    fn parse_struct(&mut self, line: i32) -> NodeStruct {
+        let name = cast!(self.expect_ident(line).token, Token::Indent);
+        self.expect(Token::OpenScope);
+        let mut fields = vec![];
+        while !self.peek_expect(0, Token::CloseScope) {
+            let ident = self.expect_ident(line);
+            self.expect(Token::Colon);
+            let kind = self.parse_type(ident.line);
+            fields.push(NodeParam { ident, kind });
+            if !self.peek_expect(0, Token::CloseScope) {
+                self.expect(Token::Comma);
             }
         }
+        self.expect(Token::CloseScope);
+        NodeStruct { name, line, fields }
+    }
 
-        funcs
+    // #[inline] or #[inline(never)], the '#' is already consumed
+    fn parse_attribute(&mut self, line: i32) -> InlineHint {
+        self.expect(Token::OpenSquare);
+        let name = self.tokens.pop_front().map(|found| found.token);
+        if name != Some(Token::Indent("inline".to_owned())) {
+            panic!("Unknown attribute {:?} at line {}", name, line);
+        }
+        let mut hint = InlineHint::Always;
+        if self.peek_expect(0, Token::OpenBracket) {
+            self.expect(Token::OpenBracket);
+            let argument = self.tokens.pop_front().map(|found| found.token);
+            if argument != Some(Token::Indent("never".to_owned())) {
+                panic!("Unknown inline argument {:?} at line {}", argument, line);
+            }
+            self.expect(Token::CloseBracket);
+            hint = InlineHint::Never;
+        }
+        self.expect(Token::CloseSquare);
+        if !self.peek_expect(0, Token::FuncSig) {
+            panic!("Attribute at line {} has to be followed by a function", line);
+        }
+        hint
     }
     
 }
 pub fn parse(tokens: VecDeque<TokenData>) -> NodeRoot {
-    let mut parser = Parser { tokens };
-    let stmts = parser.parse_file();
-    NodeRoot { funcs: stmts }
+    let mut parser = Parser { tokens, structs: HashMap::new(), enums: HashMap::new() };
+    parser.parse_file()
 }
 
 fn is_binary_operator(token: &Token) -> bool {
     match token {
         Token::Multiply | Token::Add 
         | Token::Subtract | Token::Division |
-        Token::AND | Token::OR | Token::Equality => true,
+        Token::And | Token::Or | Token::Equality => true,
         Token::GreaterThan => true,
         Token::LessThan => true,
         Token::GreaterThanEqual => true,
         Token::LessThanEqual => true,
         Token::Exit => false,
-        Token::IntLiteral(_) => false,
+        Token::IntLiteral(..) => false,
+        Token::FloatLiteral(_) => false,
+        Token::CharLiteral(_) => false,
+        Token::StringLiteral(_) => false,
         Token::BooleanLiteral(_) => false,
         Token::SemiColon => false,
         Token::Let => false,
@@ -345,6 +1052,24 @@ fn is_binary_operator(token: &Token) -> bool {
         Token::ReturnSig => false,
         Token::FuncSig => false,
         Token::LitType(_) => false,
+        Token::Hash => false,
+        Token::OpenSquare => false,
+        Token::CloseSquare => false,
+        Token::Colon => false,
+        Token::Comma => false,
+        Token::As => false,
+        Token::Print(_) => false,
+        Token::Read(_) => false,
+        Token::Struct => false,
+        Token::Dot => false,
+        Token::Enum => false,
+        Token::Match => false,
+        Token::FatArrow => false,
+        Token::PathSep => false,
+        Token::Underscore => false,
+        Token::Const => false,
+        Token::Static => false,
+        Token::Mut => false,
     }
 }
 
@@ -355,14 +1080,17 @@ fn operator_to_binary_op(token: &Token) -> NodeBiOp  {
         Token::Subtract => NodeBiOp::Subtract,
         Token::Division => NodeBiOp::Division,
         Token::Equality => NodeBiOp::Equality,
-        Token::OR => NodeBiOp::OR,
-        Token::AND => NodeBiOp::AND,
+        Token::Or => NodeBiOp::Or,
+        Token::And => NodeBiOp::And,
         Token::GreaterThan => NodeBiOp::GreaterThan,
         Token::LessThan => NodeBiOp::LessThan,
         Token::GreaterThanEqual => NodeBiOp::GreaterThanEqual,
         Token::LessThanEqual => NodeBiOp::LessThanEqual,
         Token::Exit => unreachable!(),
-        Token::IntLiteral(_) => unreachable!(),
+        Token::IntLiteral(..) => unreachable!(),
+        Token::FloatLiteral(_) => unreachable!(),
+        Token::CharLiteral(_) => unreachable!(),
+        Token::StringLiteral(_) => unreachable!(),
         Token::BooleanLiteral(_) => unreachable!(),
         Token::SemiColon => unreachable!(),
         Token::Let => unreachable!(),
@@ -378,5 +1106,23 @@ fn operator_to_binary_op(token: &Token) -> NodeBiOp  {
         Token::ReturnSig => unreachable!(),
         Token::FuncSig => unreachable!(),
         Token::LitType(_) => unreachable!(),
+        Token::Hash => unreachable!(),
+        Token::OpenSquare => unreachable!(),
+        Token::CloseSquare => unreachable!(),
+        Token::Colon => unreachable!(),
+        Token::Comma => unreachable!(),
+        Token::As => unreachable!(),
+        Token::Print(_) => unreachable!(),
+        Token::Read(_) => unreachable!(),
+        Token::Struct => unreachable!(),
+        Token::Dot => unreachable!(),
+        Token::Enum => unreachable!(),
+        Token::Match => unreachable!(),
+        Token::FatArrow => unreachable!(),
+        Token::PathSep => unreachable!(),
+        Token::Underscore => unreachable!(),
+        Token::Const => unreachable!(),
+        Token::Static => unreachable!(),
+        Token::Mut => unreachable!(),
     }
 }
@@ -1,9 +1,490 @@
+use hydrogen::{Emit, OptLevel, Options};
 
 
 #[test]
 fn test_add() {
-    let result = hydrogen::main("test_files/if_condition_basic.bk".to_owned());
+    let result = hydrogen::main("test_files/if_condition_basic.bk".to_owned(), Options::default());
     assert!(result.is_ok(), "Result is error");
     let result = result.unwrap();
     assert_eq!(result, "1");
 }
+
+#[test]
+#[should_panic(expected = "Division by zero")]
+fn test_constant_division_by_zero() {
+    let _ = hydrogen::main("test_files/division_by_zero.bk".to_owned(), Options::default());
+}
+
+#[test]
+#[should_panic(expected = "does not fit in i32")]
+fn test_literal_out_of_range() {
+    let _ = hydrogen::main("test_files/literal_overflow.bk".to_owned(), Options::default());
+}
+
+#[test]
+#[should_panic(expected = "Arithmetic overflow")]
+fn test_constant_overflow() {
+    let _ = hydrogen::main("test_files/constant_overflow.bk".to_owned(), Options::default());
+}
+
+#[test]
+fn test_runtime_checks() {
+    let content = std::fs::read_to_string("test_files/runtime_checks.bk").unwrap();
+
+    let checked = hydrogen::compile(&content, &Options { runtime_checks: true, ..Options::default() });
+    assert!(checked.contains(", SXTW\nB.NE _bk_trap_overflow"));
+    assert!(checked.contains(", _bk_trap_div_zero\nSDIV "));
+    assert!(checked.contains("_bk_trap:"));
+
+    let unchecked = hydrogen::compile(&content, &Options::default());
+    assert!(!unchecked.contains("_bk_trap"));
+}
+
+#[test]
+fn test_emit_ir() {
+    let content = std::fs::read_to_string("test_files/if_condition_basic.bk").unwrap();
+    let options = Options { emit: Emit::Ir, ..Options::default() };
+    let ir = hydrogen::compile(&content, &options);
+
+    assert!(ir.starts_with("fn main() -> i32 {\n  %a: i32\n"));
+    assert!(ir.contains("  v5: bool = eq v3, v4\n"));
+    assert!(ir.contains("  br v9, bb1, bb2\nbb1:\n  v10: i32 = load %a\n  ret v10\n"));
+}
+
+fn instruction_count(assembly: &str) -> usize {
+    assembly
+        .lines()
+        .filter(|line| !(line.is_empty() || line.starts_with(';') || line.starts_with('.') || line.ends_with(':')))
+        .count()
+}
+
+#[test]
+fn test_register_allocation() {
+    let content = std::fs::read_to_string("test_files/if_condition_basic.bk").unwrap();
+    let assembly = hydrogen::compile(&content, &Options::default());
+
+    // Temporaries no longer go through the stack
+    assert!(!assembly.contains("X9, [SP, #-16]!"));
+    // 52 instructions with the stack machine, two of the rest set up the frame pointer
+    assert!(instruction_count(&assembly) <= 32);
+}
+
+#[test]
+fn test_frame_layout() {
+    let content = std::fs::read_to_string("test_files/many_locals.bk").unwrap();
+    let assembly = hydrogen::compile(&content, &Options::default());
+
+    // The whole frame is reserved once and slots are addressed from FP
+    assert!(assembly.contains("MOV X29, SP\nSUB SP, SP, #"));
+    assert_eq!(assembly.matches("SUB SP, SP").count(), 1);
+    assert!(assembly.contains(", [X29, #-"));
+    // Only the frame record is pushed relative to SP
+    assert_eq!(assembly.matches("[SP, #").count(), 1);
+}
+
+#[test]
+fn test_peephole() {
+    let content = std::fs::read_to_string("test_files/compare_branch.bk").unwrap();
+    let assembly = hydrogen::compile(&content, &Options::default());
+
+    // if (0) and its body are gone, folded before code generation
+    assert!(!assembly.contains("B.EQ main_bb"));
+    assert!(!assembly.contains("#7"));
+    // a < b branches on the comparison itself
+    assert!(assembly.contains("B.GE main_bb2"));
+    assert!(!assembly.contains("CSET"));
+}
+
+#[test]
+fn test_peephole_large_function() {
+    // Rewrites used to restart from the top of the program, which took minutes here
+    let conditions: String = (0..1500).map(|i| format!("    if (x > {}) {{\n        y = y + 1;\n    }}\n", i)).collect();
+    let content = format!("fn main() -> i32 {{\n    let x = 7;\n    let y = 0;\n{}    return y;\n}}\n", conditions);
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // Every condition still branches on the comparison itself
+    assert_eq!(assembly.matches("B.LE main_bb").count(), 1500);
+}
+
+#[test]
+fn test_dead_code_elimination() {
+    let content = std::fs::read_to_string("test_files/dead_code.bk").unwrap();
+    let options = Options { emit: Emit::Ir, ..Options::default() };
+    let ir = hydrogen::compile(&content, &options);
+
+    assert!(!ir.contains("fn unused()"));
+    assert!(ir.contains("fn helper()"));
+    // Only x is ever read, the ignored call still happens
+    assert!(ir.contains("fn main() -> i32 {\n  %x: i32\nbb0:\n"));
+    assert_eq!(ir.matches("call helper()").count(), 2);
+    assert!(!ir.contains("const 4"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    assert!(!assembly.contains("_unused:"));
+}
+
+#[test]
+fn test_inlining() {
+    let content = std::fs::read_to_string("test_files/inline.bk").unwrap();
+    let options = Options { emit: Emit::Ir, ..Options::default() };
+    let ir = hydrogen::compile(&content, &options);
+
+    // Small and #[inline] functions disappear into their callers
+    assert!(!ir.contains("call bar()"));
+    assert!(!ir.contains("fn bar()"));
+    assert!(!ir.contains("call big()"));
+    assert!(ir.contains("  %big.a: i32\n"));
+    // #[inline(never)] and functions over the size limit are still called
+    assert!(ir.contains("#[inline(never)]\nfn baz() -> i32 {"));
+    assert!(ir.contains("call baz()"));
+    assert!(ir.contains("call pick()"));
+}
+
+#[test]
+fn test_tail_calls() {
+    let content = std::fs::read_to_string("test_files/countdown.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("tailcall countdown("));
+
+    // The recursive call jumps after tearing down the frame, main still calls
+    let assembly = hydrogen::compile(&content, &Options::default());
+    assert!(assembly.contains("LDP X29, X30, [SP], #16\nB _countdown\n"));
+    assert_eq!(assembly.matches("BL _countdown").count(), 1);
+}
+
+#[test]
+#[cfg_attr(not(target_os = "macos"), ignore = "runs the program, which needs macOS on AArch64")]
+fn test_deep_tail_recursion() {
+    // A million frames would overflow the 8 MiB stack
+    let result = hydrogen::main("test_files/countdown.bk".to_owned(), Options::default());
+    assert_eq!(result.unwrap(), "0");
+}
+
+#[test]
+fn test_optimization_levels() {
+    let content = std::fs::read_to_string("test_files/inline.bk").unwrap();
+    let compile = |opt_level, emit| hydrogen::compile(&content, &Options { opt_level, emit, ..Options::default() });
+
+    // -O0 keeps every call and every value on the stack
+    assert!(compile(OptLevel::O0, Emit::Ir).contains("call bar()"));
+    let unoptimized = compile(OptLevel::O0, Emit::Asm);
+    assert!(!unoptimized.contains("X12"));
+
+    // -O1 folds and allocates registers, inlining waits for -O2
+    assert!(compile(OptLevel::O1, Emit::Ir).contains("call bar()"));
+    assert!(!compile(OptLevel::O2, Emit::Ir).contains("call bar()"));
+    let optimized = compile(OptLevel::O2, Emit::Asm);
+    assert!(instruction_count(&optimized) < instruction_count(&unoptimized));
+}
+
+#[test]
+fn test_integer_types() {
+    let content = std::fs::read_to_string("test_files/integer_types.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("fn add8(%a: u8, %b: u8) -> u8 {"));
+    // Untyped literals take the type of their context
+    assert!(ir.contains("v1: u64 = const 2\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // u8 sums wrap, unsigned values compare and divide unsigned
+    assert!(assembly.contains("AND X14, X14, #255"));
+    assert!(assembly.contains("CSET X14, LO"));
+    assert!(assembly.contains("UDIV "));
+}
+
+#[test]
+#[should_panic(expected = "Mismatched types: u8 Add i32")]
+fn test_mismatched_types() {
+    let content = std::fs::read_to_string("test_files/mismatched_types.bk").unwrap();
+    hydrogen::compile(&content, &Options::default());
+}
+
+#[test]
+fn test_operator_precedence() {
+    let content = std::fs::read_to_string("test_files/precedence.bk").unwrap();
+    let options = Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() };
+    let ir = hydrogen::compile(&content, &options);
+
+    // && binds tighter than ||, * tighter than +
+    assert!(ir.find(" = and ").unwrap() < ir.find(" = or ").unwrap());
+    assert!(ir.find(" = mul ").unwrap() < ir.find(" = add ").unwrap());
+    // `a>3` keeps its 3
+    assert!(ir.contains("const 3\n"));
+}
+
+#[test]
+fn test_casts() {
+    let content = std::fs::read_to_string("test_files/casts.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("v1: u8 = cast v0\n"));
+    // 260 as u8 wraps at compile time
+    assert!(ir.contains("u8 = const 4\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // Narrowing keeps the low bits, extended the way the target type is
+    assert!(assembly.contains("AND X13, X12, #255"));
+    assert!(assembly.contains("SXTW X13, W12"));
+}
+
+#[test]
+#[should_panic(expected = "Cannot cast i32 to bool")]
+fn test_invalid_cast() {
+    let content = "fn main() -> i32 {\n    let a = 3;\n    return (a as bool) as i32;\n}\n";
+    hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_floats() {
+    let content = std::fs::read_to_string("test_files/floats.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("fn area(%r: f64) -> f64 {"));
+    assert!(ir.contains("f64 = const 0.001\n"));
+    // 0.0 / 0.0 folds to NaN
+    assert!(ir.contains("f64 = const NaN\n"));
+
+    let options = Options { opt_level: OptLevel::O0, ..Options::default() };
+    let assembly = hydrogen::compile(&content, &options);
+    assert!(assembly.contains("FMUL D16, D16, D17"));
+    assert!(assembly.contains("SCVTF D16, "));
+    // Comparisons are false on NaN
+    assert!(assembly.contains("FCMP D16, D17\nCSET X11, MI"));
+    // 3.14159 does not fit a MOV immediate
+    assert!(assembly.contains("MOVK X11, #16393, LSL #48"));
+}
+
+#[test]
+#[should_panic(expected = "Mismatched types: i32 Add f64")]
+fn test_mixed_float_arithmetic() {
+    let content = "fn main() -> i32 {\n    let a = 1 + 2.0;\n    return 0;\n}\n";
+    hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_integer_literals() {
+    let content = std::fs::read_to_string("test_files/literals.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    // Hex, binary, octal and separated literals reach the IR as plain numbers
+    assert!(ir.contains("i32 = const 255\n"));
+    assert!(ir.contains("i32 = const 170\n"));
+    assert!(ir.contains("i32 = const 493\n"));
+    assert!(ir.contains("u64 = const 16045690981097406465\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // 0xDEAD_BEEF_0000_0001 is built 16 bits at a time, -100_000 from all ones
+    assert!(assembly.contains("#1\nMOVK X12, #48879, LSL #32\nMOVK X12, #57005, LSL #48\n"));
+    assert!(assembly.contains("MOVN X13, #34463\nMOVK X13, #65534, LSL #16\n"));
+}
+
+#[test]
+fn test_char_and_string_literals() {
+    let content = std::fs::read_to_string("test_files/text.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    // Both uses of the literal share one entry
+    assert!(ir.starts_with("@0 = \"héllo, \\\"world\\\"\\n\"\n\n"));
+    assert!(!ir.contains("@1 ="));
+    assert!(ir.contains("fn isdigit(%c: char) -> bool {"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    assert!(assembly.contains("ADRP X12, _bk_str0@PAGE\nADD X12, X12, _bk_str0@PAGEOFF\n"));
+    // Read-only length then bytes, UTF-8 and escapes written in octal
+    assert!(assembly.contains(".const\n.p2align 3\n_bk_str0:\n.quad 16\n.ascii \"h\\303\\251llo, \\\"world\\\"\\012\"\n"));
+}
+
+#[test]
+fn test_print() {
+    let content = std::fs::read_to_string("test_files/print.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("  v1: str = string @0\n  println v1\n"));
+    assert!(ir.contains("  print v2\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // Integers go through the runtime's decimal conversion, signed ones with X1 = 1
+    assert!(assembly.contains("MOV X1, #1\nBL _bk_print_int\n"));
+    assert!(assembly.contains("MOV X1, #0\nBL _bk_print_int\n"));
+    assert!(assembly.contains("BL _bk_print_bool\n"));
+    assert!(assembly.contains("_bk_print_int:\n"));
+    assert!(assembly.contains("_bk_newline:\n.quad 1\n.ascii \"\\012\"\n"));
+}
+
+#[test]
+fn test_read() {
+    let content = std::fs::read_to_string("test_files/read.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    assert!(ir.contains("  v0: str = read\n"));
+    assert!(ir.contains("  v4: i64 = read\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    assert!(assembly.contains("BL _bk_read_line\n"));
+    assert!(assembly.contains("BL _bk_read_int\n"));
+    // The input buffer and the lines live in zero initialized memory
+    assert!(assembly.contains(".bss\n.p2align 3\n_bk_input:\n.space 4112\n"));
+}
+
+#[test]
+fn test_arrays() {
+    let content = std::fs::read_to_string("test_files/arrays.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    assert!(ir.contains("  %squares: [i64; 4]\n"));
+    assert!(ir.contains("  fill %squares, v0\n"));
+    assert!(ir.contains("  store %primes[v4], v0\n"));
+    assert!(ir.contains(": i32 = load %primes["));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // Elements are addressed from the first one, scaled by their 8 bytes
+    assert!(assembly.contains(", [X17, X"));
+    assert!(assembly.contains(", LSL #3]\n"));
+    assert!(assembly.contains("CBNZ X10, sum_fill1\n"));
+    assert!(!assembly.contains("_bk_trap_bounds"));
+
+    let checked = hydrogen::compile(&content, &Options { runtime_checks: true, ..Options::default() });
+    assert!(checked.contains(", #4\nB.HS _bk_trap_bounds\nSUB X17, X29, #32\n"));
+}
+
+#[test]
+#[should_panic(expected = "Index 3 is out of bounds for a of length 3")]
+fn test_constant_index_out_of_bounds() {
+    let content = std::fs::read_to_string("test_files/index_out_of_bounds.bk").unwrap();
+    let _ = hydrogen::compile(&content, &Options::default());
+}
+
+#[test]
+fn test_structs() {
+    let content = std::fs::read_to_string("test_files/structs.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    // Fields are laid out in declaration order at their natural alignment
+    assert!(ir.starts_with("struct.0 = Rect { origin: struct.1 +0, size: struct.1 +8, filled: bool +16 }, 20 bytes\n"));
+    // A returned struct is written through a hidden first parameter
+    assert!(ir.contains("fn grow(%ret: ptr, %r: ptr, %by: i32) -> ptr {"));
+    assert!(ir.contains("  store [v1 + 0], v6\n"));
+    // The callee gets a copy of r
+    assert!(ir.contains("  copy v14, v13, 20\n  v15: i32 = const 1\n  v16: ptr = call grow(v12, v14, v15)\n"));
+    // Swapping copies both fields of r aside before writing either
+    assert!(ir.contains("  v27: ptr = addr %Point.tmp.2\n  copy v27, v26, 8\n"));
+    assert!(ir.contains("  copy v33, v27, 8\n  v34: ptr = addr [v24 + 8]\n  copy v34, v30, 8\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    assert!(assembly.contains("LDRSW X"));
+    assert!(assembly.contains("STRB W"));
+    assert!(assembly.contains("LDR W11, [X"));
+}
+
+#[test]
+#[should_panic(expected = "Field y of Point is missing")]
+fn test_missing_field() {
+    let content = "struct Point {\n    x: i32,\n    y: i32,\n}\n\nfn main() -> i32 {\n    let p = Point { x: 1 };\n    return p.x;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_enums() {
+    let content = std::fs::read_to_string("test_files/enums.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    // Variants holding values overlap after the tag
+    assert!(ir.contains("struct.1 = Shape { tag: u32 +0, Circle.0: i32 +4, Rect.0: struct.0 +4, Rect.1: bool +12 }, 16 bytes\n"));
+    // Without `_` the last arm takes the remaining tag
+    assert!(ir.contains("  v1: u32 = load [v0 + 0]\n  switch v1, [0: bb1, 1: bb2], bb3\n"));
+    assert!(ir.contains("  switch v0, [-1: bb1, 10: bb2, 5: bb3], bb4\n"));
+    // The payload is copied aside before the tag of square is written
+    assert!(ir.contains("  copy v13, v12, 8\n  v14: bool = const 0\n  v15: u32 = const 1\n  store [v7 + 0], v15\n"));
+    assert!(ir.contains("  copy v16, v13, 8\n"));
+
+    let assembly = hydrogen::compile(&content, &Options::default());
+    // Five consecutive days go through a table, three scattered scores through compares
+    assert!(assembly.contains("CMP X12, #5\nB.HS hours_bb6\nADR X11, hours_table1\n"));
+    assert!(assembly.contains("hours_table1:\n.quad hours_bb1 - hours_table1\n"));
+    assert!(assembly.contains("CMN X12, #1\nB.EQ grade_bb1\nCMP X12, #10\nB.EQ grade_bb2\n"));
+}
+
+#[test]
+#[should_panic(expected = "Match at line 8 does not cover Coin::Dime")]
+fn test_non_exhaustive_match() {
+    let content = "enum Coin {\n    Penny,\n    Dime,\n}\n\nfn main() -> i32 {\n    let coin = Coin::Dime;\n    match coin {\n        Coin::Penny => {\n            return 1;\n        }\n    }\n    return 0;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_if_expressions() {
+    let content = std::fs::read_to_string("test_files/if_expr.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    // Every branch stores to the same local, read once they join
+    assert!(ir.contains("bb4:\n  v10: i64 = const 1\n  store %if, v10\n  jmp bb5\nbb5:\n  v11: i64 = load %if\n  ret v11\n"));
+    // A struct valued if writes straight to where the result goes
+    assert!(ir.contains("bb1:\n  v2: ptr = load %a\n  copy v0, v2, 8\n  jmp bb3\n"));
+    // The statements of a block run before its value
+    assert!(ir.contains("  store %base, v0\n  v1: i32 = load %base\n  v2: i32 = const 2\n  v3: i32 = mul v1, v2\n  store %limit, v3\n"));
+}
+
+#[test]
+#[should_panic(expected = "Branches of the if at line 2 give i32 and bool")]
+fn test_if_branches_disagree() {
+    let content = "fn main() -> i32 {\n    let x = if (true) { 1 } else { false };\n    return x;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_expression_statements() {
+    let content = std::fs::read_to_string("test_files/expr_stmt.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, ..Options::default() });
+    // The calls stay for their side effects, the unused sum goes
+    assert!(ir.contains("  v1: i32 = call log(v0)\n  v2: i32 = const 2\n  v3: i32 = call log(v2)\n  v6: i32 = const 3\n"));
+}
+
+#[test]
+#[should_panic(expected = "Expected a statement at line 3 but found Some(CloseBracket)")]
+fn test_unknown_statement() {
+    let content = "fn main() -> i32 {\n    let x = 1;\n    ) return x;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+#[should_panic(expected = "Expected a statement at line 2 but found Some(FuncSig)")]
+fn test_nested_function() {
+    let content = "fn main() -> i32 {\n    fn inner() -> i32 {\n        return 1;\n    }\n    return 0;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_unit_functions() {
+    let content = std::fs::read_to_string("test_files/unit.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    // `return;` and falling off the end both return nothing
+    assert!(ir.contains("bb1:\n  ret\nbb2:\n"));
+    assert!(ir.contains("  v7: unit = call countdown(v6)\n  ret\n}\n"));
+    let asm = hydrogen::compile(&content, &Options::default());
+    // A main without a return type exits with 0
+    assert!(asm.contains("MOV X0, #0\nMOV X16, #1\nSVC #128\n"));
+}
+
+#[test]
+#[should_panic(expected = "Missing return in function sign")]
+fn test_missing_return() {
+    let _ = hydrogen::main("test_files/missing_return.bk".to_owned(), Options::default());
+}
+
+#[test]
+#[should_panic(expected = "Function log does not return a value in function main")]
+fn test_unit_call_value() {
+    let content = "fn log(n: i32) {\n    println(n);\n}\n\nfn main() -> i32 {\n    let x = log(1);\n    return x;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}
+
+#[test]
+fn test_globals() {
+    let content = std::fs::read_to_string("test_files/globals.bk").unwrap();
+    let ir = hydrogen::compile(&content, &Options { emit: Emit::Ir, opt_level: OptLevel::O0, ..Options::default() });
+    // Only statics are stored, constants are used by value
+    assert!(ir.starts_with("global.0 = COUNTER: i32 = 0\nglobal.1 = FLAG: bool = 0\nglobal.2 = SEED: u64 = 7\n\n"));
+    assert!(ir.contains("  v0: i32 = load global.0\n  v1: i32 = load %step\n  v2: i32 = add v0, v1\n  store global.0, v2\n"));
+    assert!(ir.contains("  v9: i32 = const 20\n  v10: unit = call tick(v9)\n"));
+    // A local shadows the constant with the same name
+    assert!(ir.contains("  v7: i32 = load %LIMIT\n  v8: unit = call tick(v7)\n"));
+    let asm = hydrogen::compile(&content, &Options::default());
+    assert!(asm.contains("ADRP X10, _bk_global1@PAGE\nADD X10, X10, _bk_global1@PAGEOFF\nSTRB W"));
+    assert!(asm.contains(".data\n.p2align 2\n_bk_global0:\n.long 0\n.p2align 0\n_bk_global1:\n.byte 0\n"));
+}
+
+#[test]
+#[should_panic(expected = "Cannot assign to the constant N in function main")]
+fn test_assign_constant() {
+    let content = "const N: i32 = 1;\n\nfn main() -> i32 {\n    N = 2;\n    return N;\n}\n";
+    let _ = hydrogen::compile(content, &Options::default());
+}